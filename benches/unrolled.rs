@@ -0,0 +1,83 @@
+// Compares `unrolled::List` with `sixth::Queue` on small elements. Run with
+// `cargo bench --bench unrolled`.
+
+use lists::sixth;
+use lists::unrolled;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const LEN: usize = 1_000_000;
+const ROUNDS: u32 = 20;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn report<L, B, S>(name: &str, build: B, sum: S)
+where
+    B: Fn() -> L,
+    S: Fn(&L) -> u64,
+{
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let list = build();
+    let bytes = ALLOCATED.load(Ordering::Relaxed) - before;
+    drop(list);
+
+    let push = time(|| drop(black_box(build())));
+    let list = build();
+    let traverse = time(|| {
+        black_box(sum(black_box(&list)));
+    });
+
+    println!(
+        "{:<24} {:>8.2} bytes/elem {:>10.2?} push {:>10.2?} traverse",
+        name,
+        bytes as f64 / LEN as f64,
+        push,
+        traverse
+    );
+}
+
+fn main() {
+    println!("{} u32 elements, {} rounds", LEN, ROUNDS);
+    report(
+        "sixth::Queue",
+        || (0..LEN as u32).collect::<sixth::Queue<u32>>(),
+        |list| list.iter().map(|&x| x as u64).sum(),
+    );
+    report(
+        "unrolled::List<_, 16>",
+        || (0..LEN as u32).collect::<unrolled::List<u32, 16>>(),
+        |list| list.iter().map(|&x| x as u64).sum(),
+    );
+    report(
+        "unrolled::List<_, 64>",
+        || (0..LEN as u32).collect::<unrolled::List<u32, 64>>(),
+        |list| list.iter().map(|&x| x as u64).sum(),
+    );
+}
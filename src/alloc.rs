@@ -0,0 +1,116 @@
+use std::alloc::{self, Layout};
+use std::ptr::NonNull;
+
+/// Allocator used for the nodes of `second::List`, `fifth::Queue` and
+/// `sixth::Queue`. It only has to hand out and take back single nodes, so a
+/// bump, pool or counting allocator can be plugged in on stable Rust.
+///
+/// # Safety
+///
+/// `allocate` must return memory valid for `layout` until it is passed back
+/// to `deallocate` on the same value, or on a value whose `can_free_from`
+/// returned `true` for it. Clones must be able to free each other's memory,
+/// since a list split in two hands a clone of its allocator to the new half.
+/// Lists never allocate zero-sized layouts.
+pub unsafe trait NodeAlloc {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` with the same `layout`.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+
+    // Whether memory allocated through `other` may be deallocated through
+    // `self`. Lists relink nodes between each other only when this holds and
+    // otherwise move the elements into freshly allocated nodes.
+    fn can_free_from(&self, other: &Self) -> bool;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Global;
+
+unsafe impl NodeAlloc for Global {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        NonNull::new(unsafe { alloc::alloc(layout) })
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::dealloc(ptr.as_ptr(), layout)
+    }
+
+    fn can_free_from(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+pub(crate) fn alloc_node<T, A: NodeAlloc>(alloc: &A, node: T) -> NonNull<T> {
+    let ptr: NonNull<T> = alloc_uninit(alloc);
+    unsafe { ptr.as_ptr().write(node) };
+    ptr
+}
+
+pub(crate) fn alloc_uninit<T, A: NodeAlloc>(alloc: &A) -> NonNull<T> {
+    let layout = Layout::new::<T>();
+    match alloc.allocate(layout) {
+        Some(ptr) => ptr.cast(),
+        None => alloc::handle_alloc_error(layout),
+    }
+}
+
+// `ptr` must come from `alloc_node` on an allocator `alloc` can free from,
+// and must not be used afterwards.
+pub(crate) unsafe fn free_node<T, A: NodeAlloc>(alloc: &A, ptr: NonNull<T>) -> T {
+    let node = ptr.as_ptr().read();
+    dealloc_node(alloc, ptr);
+    node
+}
+
+// Like `free_node`, but for memory whose value was already moved out.
+pub(crate) unsafe fn dealloc_node<T, A: NodeAlloc>(alloc: &A, ptr: NonNull<T>) {
+    alloc.deallocate(ptr.cast(), Layout::new::<T>());
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Counts live allocations; instances only free memory from clones of
+    // themselves, so lists must fall back to moving elements between them.
+    #[derive(Clone, Default)]
+    pub struct Counting {
+        pub live: Rc<Cell<usize>>,
+        pub total: Rc<Cell<usize>>,
+    }
+
+    unsafe impl NodeAlloc for Counting {
+        fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+            self.live.set(self.live.get() + 1);
+            self.total.set(self.total.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - 1);
+            Global.deallocate(ptr, layout)
+        }
+
+        fn can_free_from(&self, other: &Self) -> bool {
+            Rc::ptr_eq(&self.live, &other.live)
+        }
+    }
+
+    #[test]
+    fn test_alloc_free_node() {
+        let alloc = Counting::default();
+        let node = alloc_node(&alloc, String::from("node"));
+        assert_eq!(alloc.live.get(), 1);
+        let value = unsafe { free_node(&alloc, node) };
+        assert_eq!(value, "node");
+        assert_eq!(alloc.live.get(), 0);
+        assert_eq!(alloc.total.get(), 1);
+        assert!(alloc.can_free_from(&alloc.clone()));
+        assert!(!alloc.can_free_from(&Counting::default()));
+    }
+}
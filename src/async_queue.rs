@@ -0,0 +1,714 @@
+use crate::fifth;
+use crate::slab::{self, Handle};
+use crate::sync::{PushError, TryPopError, TryPushError};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+// A `fifth::Queue` whose pops can wait for an element without blocking a
+// thread, and whose pushes can wait for room when the queue is bounded.
+// Only `std::task` is used, so it works under any executor.
+//
+// A task that has to wait parks its `Waker` at the back of `poppers` or
+// `pushers` and keeps the slab handle. Whoever makes progress possible
+// removes the front waker and wakes it, so a future that finds its handle
+// gone knows it was picked. If it is dropped before using its turn, it
+// passes the turn on to the next waiter, so no wake-up is lost.
+pub struct Queue<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: fifth::Queue<T>,
+    capacity: Option<usize>,
+    // Slots promised to sinks by `poll_ready` but not yet filled.
+    reserved: usize,
+    closed: bool,
+    poppers: slab::List<Waker>,
+    pushers: slab::List<Waker>,
+}
+
+// Returned by `PushSink::poll_ready` once the queue is closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Closed;
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.queue.len() + self.reserved >= capacity)
+    }
+
+    // Pushes and picks the popper to wake, which the caller wakes once the
+    // lock is released.
+    fn push(&mut self, elem: T) -> Option<Waker> {
+        self.queue.push(elem);
+        self.poppers.pop_front()
+    }
+
+    fn pop(&mut self) -> Option<(T, Option<Waker>)> {
+        let elem = self.queue.pop()?;
+        Some((elem, self.pushers.pop_front()))
+    }
+
+    // Parks the pusher while the queue is full. Pushers fill the room they
+    // were told about before releasing the lock.
+    fn poll_room(
+        &mut self,
+        waiter: &mut Option<Handle>,
+        waker: &Waker,
+    ) -> Poll<Result<(), Closed>> {
+        if self.closed {
+            unpark(&mut self.pushers, waiter);
+            Poll::Ready(Err(Closed))
+        } else if self.is_full() {
+            park(&mut self.pushers, waiter, waker);
+            Poll::Pending
+        } else {
+            unpark(&mut self.pushers, waiter);
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+fn park(waiters: &mut slab::List<Waker>, waiter: &mut Option<Handle>, waker: &Waker) {
+    match waiter.and_then(|handle| waiters.get_mut(handle)) {
+        Some(parked) => {
+            if !parked.will_wake(waker) {
+                *parked = waker.clone();
+            }
+        }
+        None => *waiter = Some(waiters.push_back(waker.clone())),
+    }
+}
+
+// Returns whether the waiter was still parked, rather than already picked.
+fn unpark(waiters: &mut slab::List<Waker>, waiter: &mut Option<Handle>) -> bool {
+    waiter
+        .take()
+        .is_some_and(|handle| waiters.remove(handle).is_some())
+}
+
+fn wake(waker: Option<Waker>) {
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Queue::with_state(fifth::Queue::new(), None)
+    }
+
+    pub fn bounded(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        Queue::with_state(fifth::Queue::new(), Some(capacity))
+    }
+
+    fn with_state(queue: fifth::Queue<T>, capacity: Option<usize>) -> Self {
+        Queue {
+            state: Mutex::new(State {
+                queue,
+                capacity,
+                reserved: 0,
+                closed: false,
+                poppers: slab::List::new(),
+                pushers: slab::List::new(),
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.lock().capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().queue.is_empty()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    pub fn try_push(&self, elem: T) -> Result<(), TryPushError<T>> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(TryPushError::Closed(elem));
+        }
+        if state.is_full() {
+            return Err(TryPushError::Full(elem));
+        }
+        let popper = state.push(elem);
+        drop(state);
+        wake(popper);
+        Ok(())
+    }
+
+    pub fn try_pop(&self) -> Result<T, TryPopError> {
+        let mut state = self.lock();
+        match state.pop() {
+            Some((elem, pusher)) => {
+                drop(state);
+                wake(pusher);
+                Ok(elem)
+            }
+            None if state.closed => Err(TryPopError::Closed),
+            None => Err(TryPopError::Empty),
+        }
+    }
+
+    // Waits for room in a bounded queue. Fails, handing the element back,
+    // once the queue is closed.
+    pub fn push(&self, elem: T) -> Push<'_, T> {
+        Push {
+            queue: self,
+            elem: Some(elem),
+            waiter: None,
+        }
+    }
+
+    // Waits for an element. Elements pushed before closing are still handed
+    // out; after that it resolves to `None`.
+    pub fn pop(&self) -> Pop<'_, T> {
+        Pop {
+            queue: self,
+            waiter: None,
+        }
+    }
+
+    pub fn stream(&self) -> PopStream<'_, T> {
+        PopStream {
+            queue: self,
+            waiter: None,
+        }
+    }
+
+    pub fn sink(&self) -> PushSink<'_, T> {
+        PushSink {
+            queue: self,
+            waiter: None,
+            reserved: false,
+        }
+    }
+
+    // Makes every later push fail and wakes every parked task.
+    pub fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        let poppers = std::mem::take(&mut state.poppers);
+        let pushers = std::mem::take(&mut state.pushers);
+        drop(state);
+        poppers.into_iter().chain(pushers).for_each(Waker::wake);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn poll_pop(&self, waiter: &mut Option<Handle>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.lock();
+        match state.pop() {
+            Some((elem, pusher)) => {
+                unpark(&mut state.poppers, waiter);
+                drop(state);
+                wake(pusher);
+                Poll::Ready(Some(elem))
+            }
+            None if state.closed => {
+                unpark(&mut state.poppers, waiter);
+                Poll::Ready(None)
+            }
+            None => {
+                park(&mut state.poppers, waiter, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn cancel_pop(&self, waiter: &mut Option<Handle>) {
+        if waiter.is_none() {
+            return;
+        }
+        let mut state = self.lock();
+        if !unpark(&mut state.poppers, waiter) && !state.queue.is_empty() {
+            let popper = state.poppers.pop_front();
+            drop(state);
+            wake(popper);
+        }
+    }
+
+    fn cancel_push(&self, waiter: &mut Option<Handle>, reserved: bool) {
+        if waiter.is_none() && !reserved {
+            return;
+        }
+        let mut state = self.lock();
+        if reserved {
+            state.reserved -= 1;
+        }
+        let picked = waiter.is_some() && !unpark(&mut state.pushers, waiter);
+        if (picked || reserved) && !state.is_full() {
+            let pusher = state.pushers.pop_front();
+            drop(state);
+            wake(pusher);
+        }
+    }
+}
+
+impl<T> fmt::Debug for Queue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("Queue")
+            .field("len", &state.queue.len())
+            .field("capacity", &state.capacity)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Pop<'a, T> {
+    queue: &'a Queue<T>,
+    waiter: Option<Handle>,
+}
+
+impl<'a, T> Future for Pop<'a, T> {
+    type Output = Option<T>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.queue.poll_pop(&mut this.waiter, cx)
+    }
+}
+
+impl<'a, T> Drop for Pop<'a, T> {
+    fn drop(&mut self) {
+        self.queue.cancel_pop(&mut self.waiter);
+    }
+}
+
+pub struct Push<'a, T> {
+    queue: &'a Queue<T>,
+    elem: Option<T>,
+    waiter: Option<Handle>,
+}
+
+// The element is only ever moved out whole, never pinned.
+impl<'a, T> Unpin for Push<'a, T> {}
+
+impl<'a, T> Future for Push<'a, T> {
+    type Output = Result<(), PushError<T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let elem = this.elem.take().expect("`Push` polled after completion");
+        let mut state = this.queue.lock();
+        match state.poll_room(&mut this.waiter, cx.waker()) {
+            Poll::Ready(Ok(())) => {
+                let popper = state.push(elem);
+                drop(state);
+                wake(popper);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(Closed)) => Poll::Ready(Err(PushError(elem))),
+            Poll::Pending => {
+                this.elem = Some(elem);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'a, T> Drop for Push<'a, T> {
+    fn drop(&mut self) {
+        self.queue.cancel_push(&mut self.waiter, false);
+    }
+}
+
+// Pops through `poll_next`, with the same signature as the `Stream` trait
+// of the `futures` crate, so wrapping it in that trait is a one-liner.
+pub struct PopStream<'a, T> {
+    queue: &'a Queue<T>,
+    waiter: Option<Handle>,
+}
+
+impl<'a, T> PopStream<'a, T> {
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        this.queue.poll_pop(&mut this.waiter, cx)
+    }
+}
+
+impl<'a, T> Drop for PopStream<'a, T> {
+    fn drop(&mut self) {
+        self.queue.cancel_pop(&mut self.waiter);
+    }
+}
+
+// Pushes the way the `Sink` trait of the `futures` crate does: `poll_ready`
+// waits for room and reserves it, and `start_send` fills it. Nothing is
+// buffered, so there is nothing to flush.
+pub struct PushSink<'a, T> {
+    queue: &'a Queue<T>,
+    waiter: Option<Handle>,
+    reserved: bool,
+}
+
+impl<'a, T> PushSink<'a, T> {
+    pub fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Closed>> {
+        let this = self.get_mut();
+        if this.reserved {
+            return Poll::Ready(Ok(()));
+        }
+        let mut state = this.queue.lock();
+        let ready = state.poll_room(&mut this.waiter, cx.waker());
+        if let Poll::Ready(Ok(())) = ready {
+            state.reserved += 1;
+            this.reserved = true;
+        }
+        ready
+    }
+
+    pub fn start_send(self: Pin<&mut Self>, elem: T) -> Result<(), PushError<T>> {
+        let this = self.get_mut();
+        assert!(this.reserved, "`start_send` called without `poll_ready`");
+        this.reserved = false;
+        let mut state = this.queue.lock();
+        state.reserved -= 1;
+        if state.closed {
+            return Err(PushError(elem));
+        }
+        let popper = state.push(elem);
+        drop(state);
+        wake(popper);
+        Ok(())
+    }
+
+    // Only releases this sink's reservation or place in line; the queue
+    // itself stays open for other producers until `Queue::close`.
+    pub fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Closed>> {
+        let this = self.get_mut();
+        this.queue.cancel_push(&mut this.waiter, this.reserved);
+        this.reserved = false;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, T> Drop for PushSink<'a, T> {
+    fn drop(&mut self) {
+        self.queue.cancel_push(&mut self.waiter, self.reserved);
+    }
+}
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the queue is closed")
+    }
+}
+
+impl Error for Closed {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::future::poll_fn;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+    use testdrop::TestDrop;
+
+    // The whole executor: poll on the current thread, park until woken.
+    struct Unparker(Thread);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(Unparker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    // A waker that only counts its wake-ups, for polling by hand.
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn counter() -> (Arc<Counter>, Waker) {
+        let counter = Arc::new(Counter::default());
+        (counter.clone(), Waker::from(counter))
+    }
+
+    fn wakes(counter: &Counter) -> usize {
+        counter.0.load(Ordering::SeqCst)
+    }
+
+    fn poll<F: Future + Unpin>(future: &mut F, waker: &Waker) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(waker))
+    }
+
+    #[test]
+    fn test_try_push_pop() {
+        let queue = Queue::bounded(2);
+        assert_eq!(queue.try_pop(), Err(TryPopError::Empty));
+        assert_eq!(queue.try_push(1), Ok(()));
+        assert_eq!(queue.try_push(2), Ok(()));
+        assert_eq!(queue.try_push(3), Err(TryPushError::Full(3)));
+        assert_eq!(queue.try_pop(), Ok(1));
+        queue.close();
+        assert_eq!(queue.try_push(3), Err(TryPushError::Closed(3)));
+        assert_eq!(queue.try_pop(), Ok(2));
+        assert_eq!(queue.try_pop(), Err(TryPopError::Closed));
+        assert_eq!(Queue::<i32>::new().capacity(), None);
+    }
+
+    #[test]
+    fn test_huge_bound() {
+        let queue = Queue::bounded(usize::MAX);
+        assert_eq!(queue.try_push(1), Ok(()));
+        assert_eq!(queue.try_pop(), Ok(1));
+    }
+
+    #[test]
+    fn test_pop_waits() {
+        let queue = Queue::new();
+        let (counter, waker) = counter();
+        let mut pop = queue.pop();
+        assert_eq!(poll(&mut pop, &waker), Poll::Pending);
+        assert_eq!(poll(&mut pop, &waker), Poll::Pending);
+        assert_eq!(wakes(&counter), 0);
+        queue.try_push(1).unwrap();
+        assert_eq!(wakes(&counter), 1);
+        assert_eq!(poll(&mut pop, &waker), Poll::Ready(Some(1)));
+        assert_eq!(block_on(queue.push(2)), Ok(()));
+        assert_eq!(block_on(queue.pop()), Some(2));
+    }
+
+    #[test]
+    fn test_push_waits() {
+        let queue = Queue::bounded(1);
+        let (counter, waker) = counter();
+        assert_eq!(block_on(queue.push(1)), Ok(()));
+        let mut push = queue.push(2);
+        assert_eq!(poll(&mut push, &waker), Poll::Pending);
+        assert_eq!(queue.try_pop(), Ok(1));
+        assert_eq!(wakes(&counter), 1);
+        assert_eq!(poll(&mut push, &waker), Poll::Ready(Ok(())));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_close_wakes_all() {
+        let empty = Queue::<i32>::new();
+        let full = Queue::bounded(1);
+        full.try_push(0).unwrap();
+        let (counter, waker) = counter();
+        let mut pops = vec![empty.pop(), empty.pop()];
+        let mut push = full.push(1);
+        for pop in &mut pops {
+            assert_eq!(poll(pop, &waker), Poll::Pending);
+        }
+        assert_eq!(poll(&mut push, &waker), Poll::Pending);
+
+        empty.close();
+        full.close();
+        assert_eq!(wakes(&counter), 3);
+        for pop in &mut pops {
+            assert_eq!(poll(pop, &waker), Poll::Ready(None));
+        }
+        assert_eq!(poll(&mut push, &waker), Poll::Ready(Err(PushError(1))));
+        assert_eq!(full.try_pop(), Ok(0));
+    }
+
+    #[test]
+    fn test_cancel_passes_turn() {
+        let queue = Queue::new();
+        let (first, first_waker) = counter();
+        let (second, second_waker) = counter();
+        let mut pop1 = queue.pop();
+        let mut pop2 = queue.pop();
+        assert_eq!(poll(&mut pop1, &first_waker), Poll::Pending);
+        assert_eq!(poll(&mut pop2, &second_waker), Poll::Pending);
+
+        queue.try_push(1).unwrap();
+        assert_eq!((wakes(&first), wakes(&second)), (1, 0));
+        drop(pop1);
+        assert_eq!(wakes(&second), 1);
+        assert_eq!(poll(&mut pop2, &second_waker), Poll::Ready(Some(1)));
+
+        let queue = Queue::bounded(1);
+        queue.try_push(0).unwrap();
+        let mut push1 = queue.push(1);
+        let mut push2 = queue.push(2);
+        assert_eq!(poll(&mut push1, &first_waker), Poll::Pending);
+        assert_eq!(poll(&mut push2, &second_waker), Poll::Pending);
+        queue.try_pop().unwrap();
+        assert_eq!(wakes(&first), 2);
+        drop(push1);
+        assert_eq!(wakes(&second), 2);
+        assert_eq!(poll(&mut push2, &second_waker), Poll::Ready(Ok(())));
+    }
+
+    #[test]
+    fn test_stream() {
+        let queue = Queue::bounded(4);
+        let popped = thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    block_on(queue.push(i)).unwrap();
+                }
+                queue.close();
+            });
+            block_on(async {
+                let mut stream = queue.stream();
+                let mut popped = Vec::new();
+                while let Some(elem) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                    popped.push(elem);
+                }
+                popped
+            })
+        });
+        assert_eq!(popped, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sink() {
+        let queue = Queue::bounded(1);
+        let (counter, waker) = counter();
+        let mut cx = Context::from_waker(&waker);
+        let mut sink1 = queue.sink();
+        let mut sink2 = queue.sink();
+        assert_eq!(
+            Pin::new(&mut sink1).poll_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(
+            Pin::new(&mut sink1).poll_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(Pin::new(&mut sink2).poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(queue.try_push(1), Err(TryPushError::Full(1)));
+
+        drop(sink1);
+        assert_eq!(wakes(&counter), 1);
+        assert_eq!(
+            Pin::new(&mut sink2).poll_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(Pin::new(&mut sink2).start_send(2), Ok(()));
+        assert_eq!(queue.try_pop(), Ok(2));
+
+        // Closing a sink hands its reservation on but leaves the queue open.
+        let mut sink3 = queue.sink();
+        assert_eq!(
+            Pin::new(&mut sink2).poll_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(Pin::new(&mut sink3).poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(
+            Pin::new(&mut sink2).poll_close(&mut cx),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(wakes(&counter), 2);
+        assert!(!queue.is_closed());
+        assert_eq!(
+            Pin::new(&mut sink3).poll_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(Pin::new(&mut sink3).start_send(3), Ok(()));
+        assert_eq!(queue.try_pop(), Ok(3));
+
+        queue.close();
+        assert_eq!(
+            Pin::new(&mut sink3).poll_ready(&mut cx),
+            Poll::Ready(Err(Closed))
+        );
+    }
+
+    #[test]
+    fn test_threads() {
+        let queue = Queue::bounded(4);
+        let sums: Vec<u64> = thread::scope(|s| {
+            let producers: Vec<_> = (0..4u64)
+                .map(|t| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        for i in 0..500 {
+                            block_on(queue.push(t * 1000 + i)).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            let consumers: Vec<_> = (0..3)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut sum = 0;
+                        while let Some(elem) = block_on(queue.pop()) {
+                            sum += elem;
+                        }
+                        sum
+                    })
+                })
+                .collect();
+            producers.into_iter().for_each(|p| p.join().unwrap());
+            queue.close();
+            consumers.into_iter().map(|c| c.join().unwrap()).collect()
+        });
+        let expected: u64 = (0..4u64)
+            .flat_map(|t| (0..500).map(move |i| t * 1000 + i))
+            .sum();
+        assert_eq!(sums.iter().sum::<u64>(), expected);
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let queue = Queue::bounded(2);
+        block_on(queue.push(testdrop.new_item())).unwrap();
+        block_on(queue.push(testdrop.new_item())).unwrap();
+
+        let (_, waker) = counter();
+        let mut push = queue.push(testdrop.new_item());
+        assert!(poll(&mut push, &waker).is_pending());
+        drop(push);
+        assert_eq!(1, testdrop.num_dropped_items());
+        drop(block_on(queue.pop()));
+        assert_eq!(2, testdrop.num_dropped_items());
+        drop(queue);
+        assert_eq!(3, testdrop.num_dropped_items());
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Queue<i32>>();
+        is_sync::<Queue<i32>>();
+        is_send::<Pop<'static, i32>>();
+        is_send::<Push<'static, i32>>();
+    }
+}
@@ -0,0 +1,7 @@
+mod epoch;
+mod queue;
+mod stack;
+
+pub use self::epoch::{pin, Guard};
+pub use self::queue::Queue;
+pub use self::stack::Stack;
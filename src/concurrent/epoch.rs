@@ -0,0 +1,273 @@
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+// Epoch-based reclamation. A thread pins itself to the current global epoch
+// before touching shared nodes, and a node unlinked in one epoch is only
+// freed two epochs later. The epoch can only advance when every pinned
+// thread has caught up with it, so by then no thread that could have seen
+// the node is still pinned. Because nodes are never reused while someone may
+// hold them, this also rules out ABA on compare-and-swap.
+//
+// Epochs count in steps of two so the low bit of a participant's state can
+// mark it as pinned.
+const PINNED: usize = 1;
+const STEP: usize = 2;
+
+// How much garbage a thread keeps before trying to free some of it.
+const COLLECT_AFTER: usize = 64;
+
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+// Every thread that ever pinned gets a participant. They are never freed;
+// a thread exiting hands its participant to the next thread that starts.
+static PARTICIPANTS: AtomicPtr<Participant> = AtomicPtr::new(ptr::null_mut());
+
+// Garbage left behind by threads that exited before it could be freed.
+static ORPHANS: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+
+struct Participant {
+    state: AtomicUsize,
+    in_use: AtomicBool,
+    next: *mut Participant,
+}
+
+// `free` may run on any thread, so it must only deallocate and never drop
+// values that are not `Send`.
+struct Deferred {
+    epoch: usize,
+    ptr: *mut u8,
+    free: unsafe fn(*mut u8),
+}
+
+unsafe impl Send for Deferred {}
+
+struct Local {
+    participant: &'static Participant,
+    guards: Cell<usize>,
+    garbage: RefCell<Vec<Deferred>>,
+}
+
+thread_local! {
+    static LOCAL: Local = Local::register();
+}
+
+impl Local {
+    fn register() -> Self {
+        let mut curr = PARTICIPANTS.load(Ordering::Acquire);
+        while let Some(participant) = unsafe { curr.as_ref() } {
+            if participant
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Local::new(participant);
+            }
+            curr = participant.next;
+        }
+
+        let participant = Box::into_raw(Box::new(Participant {
+            state: AtomicUsize::new(0),
+            in_use: AtomicBool::new(true),
+            next: ptr::null_mut(),
+        }));
+        let mut head = PARTICIPANTS.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*participant).next = head };
+            match PARTICIPANTS.compare_exchange_weak(
+                head,
+                participant,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Local::new(unsafe { &*participant }),
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    fn new(participant: &'static Participant) -> Self {
+        Local {
+            participant,
+            guards: Cell::new(0),
+            garbage: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        let garbage = mem::take(self.garbage.get_mut());
+        if !garbage.is_empty() {
+            ORPHANS
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend(garbage);
+        }
+        self.participant.state.store(0, Ordering::Release);
+        self.participant.in_use.store(false, Ordering::Release);
+    }
+}
+
+// Keeps the current thread pinned, so shared nodes it has loaded stay
+// allocated. Guards nest; the thread is unpinned when the last one drops.
+pub struct Guard {
+    marker: PhantomData<*mut ()>,
+}
+
+pub fn pin() -> Guard {
+    LOCAL.with(|local| {
+        let guards = local.guards.get();
+        local.guards.set(guards + 1);
+        if guards == 0 {
+            let epoch = EPOCH.load(Ordering::Relaxed);
+            local
+                .participant
+                .state
+                .store(epoch | PINNED, Ordering::Relaxed);
+            fence(Ordering::SeqCst);
+        }
+    });
+    Guard {
+        marker: PhantomData,
+    }
+}
+
+impl Guard {
+    // Schedules `free(ptr)` for when no pinned thread can still reach `ptr`.
+    // `ptr` must already be unreachable for threads that pin from now on,
+    // and `free` must be safe to call on it once, from any thread.
+    pub(crate) unsafe fn defer(&self, ptr: *mut u8, free: unsafe fn(*mut u8)) {
+        let epoch = EPOCH.load(Ordering::SeqCst);
+        let full = LOCAL.with(|local| {
+            let mut garbage = local.garbage.borrow_mut();
+            garbage.push(Deferred { epoch, ptr, free });
+            garbage.len() >= COLLECT_AFTER
+        });
+        if full {
+            collect();
+        }
+    }
+
+    // Tries to advance the epoch and frees whatever garbage has expired.
+    pub fn flush(&self) {
+        collect();
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = LOCAL.try_with(|local| {
+            let guards = local.guards.get() - 1;
+            local.guards.set(guards);
+            if guards == 0 {
+                local.participant.state.store(0, Ordering::Release);
+            }
+        });
+    }
+}
+
+fn try_advance() {
+    let epoch = EPOCH.load(Ordering::SeqCst);
+    let mut curr = PARTICIPANTS.load(Ordering::Acquire);
+    while let Some(participant) = unsafe { curr.as_ref() } {
+        let state = participant.state.load(Ordering::SeqCst);
+        if state & PINNED != 0 && state & !PINNED != epoch {
+            return;
+        }
+        curr = participant.next;
+    }
+    let _ = EPOCH.compare_exchange(epoch, epoch + STEP, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+fn collect() {
+    try_advance();
+    let epoch = EPOCH.load(Ordering::SeqCst);
+    let expired = |deferred: &Deferred| deferred.epoch + 2 * STEP <= epoch;
+
+    // The garbage is taken out before freeing anything, in case a `free`
+    // ends up deferring more.
+    let (mut ready, pending): (Vec<_>, Vec<_>) = LOCAL.with(|local| {
+        mem::take(&mut *local.garbage.borrow_mut())
+            .into_iter()
+            .partition(expired)
+    });
+    LOCAL.with(|local| local.garbage.borrow_mut().extend(pending));
+    if let Ok(mut orphans) = ORPHANS.try_lock() {
+        let (orphaned, pending) = mem::take(&mut *orphans).into_iter().partition(expired);
+        *orphans = pending;
+        ready.extend::<Vec<_>>(orphaned);
+    }
+
+    for deferred in ready {
+        unsafe { (deferred.free)(deferred.ptr) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    // Each deferred pointer is a boxed reference to the counter it bumps
+    // when freed.
+    unsafe fn free_counted(ptr: *mut u8) {
+        let counter = Box::from_raw(ptr as *mut &'static AtomicUsize);
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn counted(counter: &'static AtomicUsize) -> *mut u8 {
+        Box::into_raw(Box::new(counter)) as *mut u8
+    }
+
+    // Other tests pin and unpin concurrently, so this only waits for the
+    // epoch to move rather than expecting it to advance on every flush.
+    fn flush_until(done: impl Fn() -> bool) {
+        for _ in 0..100_000 {
+            if done() {
+                return;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        panic!("garbage was never freed");
+    }
+
+    #[test]
+    fn test_pin_nests() {
+        let outer = pin();
+        let state = || LOCAL.with(|local| local.participant.state.load(Ordering::SeqCst));
+        assert_ne!(state() & PINNED, 0);
+        let inner = pin();
+        drop(outer);
+        assert_ne!(state() & PINNED, 0);
+        drop(inner);
+        assert_eq!(state(), 0);
+    }
+
+    #[test]
+    fn test_defer() {
+        static FREED: AtomicUsize = AtomicUsize::new(0);
+        let guard = pin();
+        unsafe { guard.defer(counted(&FREED), free_counted) };
+        guard.flush();
+        assert_eq!(FREED.load(Ordering::SeqCst), 0);
+        drop(guard);
+        flush_until(|| FREED.load(Ordering::SeqCst) == 1);
+    }
+
+    #[test]
+    fn test_orphans() {
+        static FREED: AtomicUsize = AtomicUsize::new(0);
+        thread::spawn(|| {
+            let guard = pin();
+            unsafe { guard.defer(counted(&FREED), free_counted) };
+        })
+        .join()
+        .unwrap();
+        flush_until(|| FREED.load(Ordering::SeqCst) == 1);
+    }
+}
@@ -0,0 +1,328 @@
+use super::epoch::pin;
+use std::fmt;
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// The Michael–Scott queue. Like `fifth::Queue`, pushes go through `tail`
+// and pops through `head`, but `head` always points at a dummy node whose
+// successor holds the front element, so pushers and poppers never contend
+// for the same pointer. A pop turns the front node into the new dummy and
+// hands the old one to the epoch collector.
+//
+// `tail` may lag one node behind while a push is half done; any thread that
+// notices swings it forward before going on.
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    marker: PhantomData<Box<Node<T>>>,
+}
+
+// `elem` is uninitialised in the dummy, whose value was either never there
+// or already popped, so freeing a node never drops it.
+struct Node<T> {
+    elem: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn alloc(elem: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            elem,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+unsafe fn free_node<T>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut Node<T>));
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::alloc(MaybeUninit::uninit());
+        Queue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let _guard = pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+
+    pub fn push(&self, elem: T) {
+        let node = Node::alloc(MaybeUninit::new(elem));
+        let _guard = pin();
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if !next.is_null() {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            let linked = unsafe {
+                (*tail).next.compare_exchange(
+                    ptr::null_mut(),
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+            };
+            if linked.is_ok() {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            if next.is_null() {
+                return None;
+            }
+
+            // The old dummy is about to be freed, so `tail` must not be left
+            // pointing at it.
+            let tail = self.tail.load(Ordering::Relaxed);
+            if tail == head {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+            }
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe {
+                    let elem = ptr::read((*next).elem.as_ptr());
+                    guard.defer(head as *mut u8, free_node::<T>);
+                    return Some(elem);
+                }
+            }
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+pub struct IntoIter<T>(Queue<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for Queue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T> Extend<T> for Queue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy> Extend<&'a T> for Queue<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T> FromIterator<T> for Queue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = Queue::new();
+        queue.extend(iter);
+        queue
+    }
+}
+
+// Other threads may be popping, so the elements are not shown.
+impl<T> fmt::Debug for Queue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Queue").finish_non_exhaustive()
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut curr = dummy.next.load(Ordering::Relaxed);
+        while !curr.is_null() {
+            let mut node = unsafe { Box::from_raw(curr) };
+            unsafe { ptr::drop_in_place(node.elem.as_mut_ptr()) };
+            curr = *node.next.get_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+    use testdrop::TestDrop;
+
+    const THREADS: usize = 4;
+    const PER_THREAD: usize = if cfg!(miri) { 50 } else { 10_000 };
+
+    #[test]
+    fn test_push_pop() {
+        let queue = Queue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(4);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+        queue.push(5);
+        assert_eq!(queue.pop(), Some(5));
+    }
+
+    #[test]
+    fn test_iter_extend() {
+        let mut queue: Queue<i32> = vec![1, 2].into_iter().collect();
+        queue.extend(&[3, 4]);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.into_iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(format!("{:?}", Queue::<i32>::default()), "Queue { .. }");
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let queue = Queue::new();
+        for _ in 0..5 {
+            queue.push(testdrop.new_item());
+        }
+        drop(queue.pop());
+        drop(queue.pop());
+        assert_eq!(2, testdrop.num_dropped_items());
+        drop(queue);
+        assert_eq!(5, testdrop.num_dropped_items());
+    }
+
+    // Producers push `(producer, seq)` pairs while consumers pop; every
+    // consumer must see each producer's sequence numbers in increasing
+    // order, and every pair must come out exactly once.
+    #[test]
+    fn test_fifo_per_producer() {
+        let queue = Queue::new();
+        let popped: Vec<Vec<(usize, usize)>> = thread::scope(|s| {
+            for p in 0..THREADS {
+                let queue = &queue;
+                s.spawn(move || {
+                    for seq in 0..PER_THREAD {
+                        queue.push((p, seq));
+                    }
+                });
+            }
+            let consumers: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        let mut popped = Vec::new();
+                        let mut last = [None; THREADS];
+                        while popped.len() < PER_THREAD {
+                            if let Some((p, seq)) = queue.pop() {
+                                assert!(last[p] < Some(seq));
+                                last[p] = Some(seq);
+                                popped.push((p, seq));
+                            }
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            consumers.into_iter().map(|c| c.join().unwrap()).collect()
+        });
+
+        assert!(queue.is_empty());
+        let mut all: Vec<_> = popped.into_iter().flatten().collect();
+        all.sort_unstable();
+        let expected: Vec<_> = (0..THREADS)
+            .flat_map(|p| (0..PER_THREAD).map(move |seq| (p, seq)))
+            .collect();
+        assert_eq!(all, expected);
+    }
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_stress_drops() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let queue = Queue::new();
+        thread::scope(|s| {
+            for t in 0..THREADS {
+                let (queue, dropped) = (&queue, &dropped);
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        queue.push(Counted(dropped.clone()));
+                        if (t + i) % 3 == 0 {
+                            drop(queue.pop());
+                        }
+                    }
+                });
+            }
+        });
+        let popped = dropped.load(Ordering::SeqCst);
+        assert!(popped > 0);
+        drop(queue);
+        assert_eq!(dropped.load(Ordering::SeqCst), THREADS * PER_THREAD);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Queue<i32>>();
+        is_sync::<Queue<std::cell::Cell<i32>>>();
+    }
+}
@@ -0,0 +1,336 @@
+use super::epoch::{pin, Guard};
+use crate::second;
+use std::fmt;
+use std::iter::{self, FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// The Treiber stack: `second::List` with the head swapped by
+// compare-and-swap. Popped nodes are handed to the epoch collector, since
+// other threads may still be reading their `next`.
+pub struct Stack<T> {
+    head: AtomicPtr<Node<T>>,
+    marker: PhantomData<Box<Node<T>>>,
+}
+
+// Popping moves `elem` out and leaves the node to the collector, which only
+// frees the memory.
+struct Node<T> {
+    elem: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+unsafe fn free_node<T>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut Node<T>));
+}
+
+unsafe fn free_chain<T>(ptr: *mut u8) {
+    let mut curr = ptr as *mut Node<T>;
+    while !curr.is_null() {
+        let node = Box::from_raw(curr);
+        curr = node.next;
+    }
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    pub fn push(&self, elem: T) {
+        let node = Box::into_raw(Box::new(Node {
+            elem: ManuallyDrop::new(elem),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = pin();
+        let mut head = self.head.load(Ordering::Acquire);
+        while !head.is_null() {
+            let next = unsafe { (*head).next };
+            match self
+                .head
+                .compare_exchange_weak(head, next, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => unsafe {
+                    let elem = ptr::read(&*(*head).elem);
+                    guard.defer(head as *mut u8, free_node::<T>);
+                    return Some(elem);
+                },
+                Err(actual) => head = actual,
+            }
+        }
+        None
+    }
+
+    // The top element stays readable for as long as `guard` keeps this
+    // thread pinned, even if another thread pops it meanwhile. A pop moves
+    // the element out bit for bit, so this is only sound for `Copy`
+    // elements, which a move leaves untouched.
+    pub fn peek<'g>(&'g self, _guard: &'g Guard) -> Option<&'g T>
+    where
+        T: Copy + Sync,
+    {
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { head.as_ref() }.map(|node| &*node.elem)
+    }
+
+    // Detaches every element at once, top first.
+    pub fn take_all(&self) -> second::List<T> {
+        let guard = pin();
+        let head = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        let mut curr = head;
+        let mut list = second::List::new();
+        list.extend(iter::from_fn(|| {
+            let node = unsafe { curr.as_ref() }?;
+            curr = node.next;
+            Some(unsafe { ptr::read(&*node.elem) })
+        }));
+        if !head.is_null() {
+            unsafe { guard.defer(head as *mut u8, free_chain::<T>) };
+        }
+        list
+    }
+}
+
+unsafe impl<T: Send> Send for Stack<T> {}
+unsafe impl<T: Send> Sync for Stack<T> {}
+
+pub struct IntoIter<T>(Stack<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for Stack<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T> Extend<T> for Stack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy> Extend<&'a T> for Stack<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T> FromIterator<T> for Stack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stack = Stack::new();
+        stack.extend(iter);
+        stack
+    }
+}
+
+// Other threads may be popping, so the elements are not shown.
+impl<T> fmt::Debug for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stack").finish_non_exhaustive()
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        let mut curr = *self.head.get_mut();
+        while !curr.is_null() {
+            let mut node = unsafe { Box::from_raw(curr) };
+            unsafe { ManuallyDrop::drop(&mut node.elem) };
+            curr = node.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+    use testdrop::TestDrop;
+
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = if cfg!(miri) { 50 } else { 10_000 };
+
+    #[test]
+    fn test_push_pop() {
+        let stack = Stack::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert!(!stack.is_empty());
+        assert_eq!(stack.pop(), Some(3));
+        stack.push(4);
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn test_peek() {
+        let stack = Stack::new();
+        let guard = pin();
+        assert_eq!(stack.peek(&guard), None);
+        stack.push(1);
+        stack.push(2);
+        let top = stack.peek(&guard);
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(top, Some(&2));
+        assert_eq!(stack.peek(&guard), Some(&1));
+    }
+
+    #[test]
+    fn test_take_all() {
+        let stack: Stack<i32> = vec![1, 2, 3].into_iter().collect();
+        let list = stack.take_all();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert!(stack.is_empty());
+        assert!(stack.take_all().is_empty());
+        stack.push(4);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let stack = Stack::new();
+        for _ in 0..6 {
+            stack.push(testdrop.new_item());
+        }
+        drop(stack.pop());
+        assert_eq!(1, testdrop.num_dropped_items());
+
+        let list = stack.take_all();
+        assert_eq!(1, testdrop.num_dropped_items());
+        drop(list);
+        assert_eq!(6, testdrop.num_dropped_items());
+
+        stack.push(testdrop.new_item());
+        stack.push(testdrop.new_item());
+        drop(stack);
+        assert_eq!(8, testdrop.num_dropped_items());
+    }
+
+    #[test]
+    fn test_stress() {
+        let stack = Stack::new();
+        let popped: Vec<Vec<usize>> = thread::scope(|s| {
+            let workers: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let stack = &stack;
+                    s.spawn(move || {
+                        let mut popped = Vec::new();
+                        for i in 0..PER_THREAD {
+                            stack.push(t * PER_THREAD + i);
+                            if i % 2 == 1 {
+                                popped.extend(stack.pop());
+                            }
+                        }
+                        if t % 2 == 0 {
+                            popped.extend(stack.take_all());
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        let mut all: Vec<usize> = popped.into_iter().flatten().collect();
+        all.extend(stack.take_all());
+        all.sort_unstable();
+        assert_eq!(all, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    }
+
+    // `testdrop` is single-threaded, so the threaded test counts drops
+    // itself.
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_stress_drops() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+        thread::scope(|s| {
+            for t in 0..THREADS {
+                let (stack, dropped) = (&stack, &dropped);
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        stack.push(Counted(dropped.clone()));
+                        if (t + i) % 3 == 0 {
+                            drop(stack.pop());
+                        }
+                    }
+                });
+            }
+        });
+        let rest = stack.take_all();
+        assert_eq!(
+            dropped.load(Ordering::SeqCst),
+            THREADS * PER_THREAD - rest.len()
+        );
+        drop(rest);
+        assert_eq!(dropped.load(Ordering::SeqCst), THREADS * PER_THREAD);
+        stack.push(Counted(dropped.clone()));
+        drop(stack);
+        assert_eq!(dropped.load(Ordering::SeqCst), THREADS * PER_THREAD + 1);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Stack<i32>>();
+        is_sync::<Stack<std::cell::Cell<i32>>>();
+    }
+}
@@ -1,11 +1,31 @@
-use std::ptr;
-
-pub struct Queue<T> {
+use crate::alloc::{alloc_uninit, dealloc_node, Global, NodeAlloc};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ptr::{self, NonNull};
+
+pub struct Queue<T, A: NodeAlloc = Global> {
     head: Link<T>,
-    tail: *mut Node<T>,
+    tail: Link<T>,
+    len: usize,
+    free: Link<T>,
+    spare: usize,
+    capacity: usize,
+    alloc: A,
+    marker: PhantomData<Box<Node<T>>>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+// Nodes are allocated through the queue's `NodeAlloc` and owned by the queue
+// as a whole, so the shared `tail` pointer never aliases a `Box` that gets
+// moved around.
+//
+// Popped nodes are kept on the `free` list, linked through `next` with their
+// `elem` already moved out, as long as `len + spare` stays within the
+// `capacity` asked for through `with_capacity` or `reserve`. A queue made with
+// `new` has no capacity and frees every node as it is popped.
+type Link<T> = Option<NonNull<Node<T>>>;
 
 struct Node<T> {
     elem: T,
@@ -14,86 +34,161 @@ struct Node<T> {
 
 impl<T> Queue<T> {
     pub fn new() -> Self {
+        Queue::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Queue::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: NodeAlloc> Queue<T, A> {
+    pub fn new_in(alloc: A) -> Self {
         Queue {
             head: None,
-            tail: ptr::null_mut(),
+            tail: None,
+            len: 0,
+            free: None,
+            spare: 0,
+            capacity: 0,
+            alloc,
+            marker: PhantomData,
         }
     }
 
-    pub fn push(&mut self, elem: T) {
-        let mut new_tail = Box::new(Node {
-            elem: elem,
-            next: None,
-        });
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let mut queue = Queue::new_in(alloc);
+        queue.reserve(capacity);
+        queue
+    }
 
-        let raw_tail: *mut Node<T> = new_tail.as_mut();
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
 
-        if self.tail.is_null() {
-            self.head = Some(new_tail);
-        } else {
-            unsafe {
-                (*self.tail).next = Some(new_tail);
-            }
+    pub fn push(&mut self, elem: T) {
+        let new_tail = match self.free {
+            Some(node) => unsafe {
+                self.free = (*node.as_ptr()).next;
+                self.spare -= 1;
+                node
+            },
+            None => alloc_uninit(&self.alloc),
+        };
+        unsafe { new_tail.as_ptr().write(Node { elem, next: None }) };
+
+        match self.tail {
+            Some(tail) => unsafe { (*tail.as_ptr()).next = Some(new_tail) },
+            None => self.head = Some(new_tail),
         }
 
-        self.tail = raw_tail;
+        self.tail = Some(new_tail);
+        self.len += 1;
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        let result = self.head.take().map(|node| {
-            self.head = node.next;
-            node.elem
-        });
+        self.head.map(|node| {
+            let Node { elem, next } = unsafe { node.as_ptr().read() };
+            self.head = next;
+
+            if self.head.is_none() {
+                self.tail = None;
+            }
 
-        if self.head.is_none() {
-            self.tail = ptr::null_mut();
+            self.len -= 1;
+            if self.len + self.spare < self.capacity {
+                unsafe { self.retire(node) };
+            } else {
+                unsafe { dealloc_node(&self.alloc, node) };
+            }
+            elem
+        })
+    }
+
+    // Makes sure `additional` more elements can be pushed without allocating,
+    // and keeps that many nodes around as elements are popped.
+    pub fn reserve(&mut self, additional: usize) {
+        self.capacity = self.capacity.max(self.len + additional);
+        while self.spare < additional {
+            unsafe { self.retire(alloc_uninit(&self.alloc)) };
+        }
+    }
+
+    // Frees every spare node and stops recycling popped nodes until the
+    // next `reserve`.
+    pub fn shrink_to_fit(&mut self) {
+        self.capacity = 0;
+        while let Some(node) = self.free {
+            unsafe {
+                self.free = (*node.as_ptr()).next;
+                dealloc_node(&self.alloc, node);
+            }
         }
+        self.spare = 0;
+    }
 
-        result
+    pub fn spare_capacity(&self) -> usize {
+        self.spare
     }
 
     pub fn is_empty(&self) -> bool {
         self.head.is_none()
     }
 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[deprecated(note = "use `len` instead")]
     pub fn size(&self) -> i32 {
-        let mut result = 0;
-        let mut curr = &self.head;
-        while let Some(node) = curr {
-            curr = &node.next;
-            result += 1;
-        }
-        result
+        self.len as i32
     }
 
     pub fn peek(&self) -> Option<&T> {
-        self.head.as_ref().map(|node| &node.elem)
+        self.head.map(|node| unsafe { &(*node.as_ptr()).elem })
     }
 
     pub fn peek_mut(&mut self) -> Option<&mut T> {
-        self.head.as_mut().map(|node| &mut node.elem)
-    }
-
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter(self)
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).elem })
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            next: self.head.as_ref().map(|node| &**node),
+            next: self.head,
+            marker: PhantomData,
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
-            next: self.head.as_mut().map(|node| &mut **node),
+            next: self.head,
+            marker: PhantomData,
         }
     }
+
+    // Pushes a node whose `elem` is uninitialized onto the free list.
+    unsafe fn retire(&mut self, node: NonNull<Node<T>>) {
+        ptr::addr_of_mut!((*node.as_ptr()).next).write(self.free);
+        self.free = Some(node);
+        self.spare += 1;
+    }
+}
+
+// Same bounds as std's `LinkedList`: the queue owns its `T`s outright.
+unsafe impl<T: Send, A: NodeAlloc + Send> Send for Queue<T, A> {}
+unsafe impl<T: Sync, A: NodeAlloc + Sync> Sync for Queue<T, A> {}
+
+impl<T, A: NodeAlloc> IntoIterator for Queue<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
 }
 
-pub struct IntoIter<T>(Queue<T>);
+pub struct IntoIter<T, A: NodeAlloc = Global>(Queue<T, A>);
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A: NodeAlloc> Iterator for IntoIter<T, A> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
         self.0.pop()
@@ -101,52 +196,148 @@ impl<T> Iterator for IntoIter<T> {
 }
 
 pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+    next: Link<T>,
+    marker: PhantomData<&'a Node<T>>,
 }
 
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_ref().map(|node| &**node);
+        self.next.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            self.next = node.next;
             &node.elem
         })
     }
 }
 
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
 pub struct IterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
+    next: Link<T>,
+    marker: PhantomData<&'a mut Node<T>>,
 }
 
 impl<'a, T> Iterator for IterMut<'a, T> {
     type Item = &'a mut T;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.next.as_mut().map(|node| &mut **node);
+        self.next.map(|node| unsafe {
+            let node = &mut *node.as_ptr();
+            self.next = node.next;
             &mut node.elem
         })
     }
 }
 
-impl<T> Drop for Queue<T> {
-    fn drop(&mut self) {
-        let mut curr_link = self.head.take();
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+impl<'a, T, A: NodeAlloc> IntoIterator for &'a Queue<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: NodeAlloc> IntoIterator for &'a mut Queue<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
 
-        while let Some(mut boxed_link) = curr_link {
-            curr_link = boxed_link.next.take();
+impl<T, A: NodeAlloc> Extend<T> for Queue<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
         }
     }
 }
 
+impl<'a, T: 'a + Copy, A: NodeAlloc> Extend<&'a T> for Queue<T, A> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T, A: NodeAlloc + Default> FromIterator<T> for Queue<T, A> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = Queue::new_in(A::default());
+        queue.extend(iter);
+        queue
+    }
+}
+
+impl<T: Clone, A: NodeAlloc + Clone> Clone for Queue<T, A> {
+    fn clone(&self) -> Self {
+        let mut queue = Queue::new_in(self.alloc.clone());
+        queue.extend(self.iter().cloned());
+        queue
+    }
+}
+
+impl<T: fmt::Debug, A: NodeAlloc> fmt::Debug for Queue<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq, A: NodeAlloc> PartialEq for Queue<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, A: NodeAlloc> Eq for Queue<T, A> {}
+
+impl<T: PartialOrd, A: NodeAlloc> PartialOrd for Queue<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord, A: NodeAlloc> Ord for Queue<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash, A: NodeAlloc> Hash for Queue<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<T, A: NodeAlloc + Default> Default for Queue<T, A> {
+    fn default() -> Self {
+        Queue::new_in(A::default())
+    }
+}
+
+impl<T, A: NodeAlloc> Drop for Queue<T, A> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+        self.shrink_to_fit();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::alloc::test::Counting;
+    use std::collections::hash_map::DefaultHasher;
 
     #[test]
     fn test_empty_on_creation() {
         let queue: Queue<i32> = Queue::new();
         assert!(queue.is_empty());
-        assert_eq!(queue.size(), 0);
+        assert_eq!(queue.len(), 0);
     }
 
     #[test]
@@ -156,14 +347,14 @@ mod test {
         queue.push(2);
         queue.push(3);
         queue.push(4);
-        assert_eq!(queue.size(), 4);
+        assert_eq!(queue.len(), 4);
         queue.pop();
-        assert_eq!(queue.size(), 3);
+        assert_eq!(queue.len(), 3);
         queue.pop();
         queue.pop();
         queue.pop();
         assert!(queue.is_empty());
-        assert_eq!(queue.size(), 0);
+        assert_eq!(queue.len(), 0);
     }
 
     #[test]
@@ -200,6 +391,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::option_map_unit_fn)]
     fn test_peek_mut() {
         let mut queue = Queue::new();
         queue.push(1);
@@ -256,4 +448,169 @@ mod test {
         assert_eq!(queue.pop(), Some(4));
         assert_eq!(queue.pop(), Some(2));
     }
+
+    #[test]
+    fn test_collect_extend() {
+        let mut queue: Queue<i32> = vec![1, 2, 3].into_iter().collect();
+        queue.extend(vec![4, 5]);
+        queue.extend(&[6]);
+        for elem in &mut queue {
+            *elem *= 10;
+        }
+        let mut elems = Vec::new();
+        for &elem in &queue {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![10, 20, 30, 40, 50, 60]);
+        let mut elems = Vec::new();
+        for elem in queue {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![10, 20, 30, 40, 50, 60]);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let queue: Queue<i32> = vec![1, 2, 3].into_iter().collect();
+        let copy = queue.clone();
+        assert_eq!(queue, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", Queue::<i32>::default()), "[]");
+        assert_eq!(hash_of(&queue), hash_of(&copy));
+
+        let shorter: Queue<i32> = vec![1, 2].into_iter().collect();
+        let bigger: Queue<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(queue, shorter);
+        assert!(shorter < queue);
+        assert!(queue < bigger);
+        assert_eq!(queue.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Queue<i32>>();
+        is_sync::<Queue<i32>>();
+        is_send::<IntoIter<i32>>();
+        is_sync::<IntoIter<i32>>();
+        is_send::<Iter<'static, i32>>();
+        is_sync::<Iter<'static, i32>>();
+        is_send::<IterMut<'static, i32>>();
+        is_sync::<IterMut<'static, i32>>();
+
+        let mut queue: Queue<String> = Queue::new();
+        queue.push(String::from("job"));
+        let handle = std::thread::spawn(move || queue.pop());
+        assert_eq!(handle.join().unwrap(), Some(String::from("job")));
+    }
+
+    #[test]
+    fn test_covariance() {
+        fn queue<'a>(queue: Queue<&'static str>) -> Queue<&'a str> {
+            queue
+        }
+        fn into_iter<'a>(iter: IntoIter<&'static str>) -> IntoIter<&'a str> {
+            iter
+        }
+        fn iter<'i, 'a>(iter: Iter<'i, &'static str>) -> Iter<'i, &'a str> {
+            iter
+        }
+
+        let mut static_queue = Queue::new();
+        static_queue.push("static");
+        let local = String::from("local");
+        let mut queue = queue(static_queue);
+        queue.push(&local);
+        assert_eq!(queue.into_iter().last(), Some("local"));
+
+        let statics: Queue<&'static str> = vec!["static"].into_iter().collect();
+        assert_eq!(iter(statics.iter()).count(), 1);
+        assert_eq!(into_iter(statics.into_iter()).next(), Some("static"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_size_alias() {
+        let mut queue = Queue::new();
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.size(), 2);
+    }
+
+    #[test]
+    fn test_node_alloc() {
+        let alloc = Counting::default();
+        let mut queue = Queue::new_in(alloc.clone());
+        queue.push(1);
+        queue.push(2);
+        queue.extend(vec![3, 4]);
+        assert_eq!(alloc.live.get(), 4);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(alloc.live.get(), 3);
+
+        let copy = queue.clone();
+        assert!(copy.allocator().can_free_from(&alloc));
+        assert_eq!(alloc.live.get(), 6);
+        drop(queue);
+        assert_eq!(copy.into_iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(alloc.live.get(), 0);
+        assert_eq!(alloc.total.get(), 7);
+    }
+
+    #[test]
+    fn test_recycle_nodes() {
+        let alloc = Counting::default();
+        let mut queue = Queue::with_capacity_in(4, alloc.clone());
+        assert_eq!(alloc.live.get(), 4);
+        assert_eq!(queue.spare_capacity(), 4);
+
+        for round in 0..10 {
+            queue.extend(vec![round; 3]);
+            assert_eq!(queue.spare_capacity(), 1);
+            assert_eq!(queue.pop(), Some(round));
+            assert_eq!(queue.pop(), Some(round));
+            queue.pop();
+        }
+        assert_eq!(alloc.total.get(), 4);
+
+        queue.extend(1..=6);
+        assert_eq!(alloc.live.get(), 6);
+        queue.pop();
+        queue.pop();
+        queue.pop();
+        assert_eq!(queue.spare_capacity(), 1);
+        assert_eq!(alloc.live.get(), 4);
+        drop(queue);
+        assert_eq!(alloc.live.get(), 0);
+    }
+
+    #[test]
+    fn test_reserve_shrink() {
+        let mut queue = Queue::new();
+        queue.push(String::from("a"));
+        queue.pop();
+        assert_eq!(queue.spare_capacity(), 0);
+
+        queue.push(String::from("a"));
+        queue.reserve(2);
+        assert_eq!(queue.spare_capacity(), 2);
+        queue.reserve(1);
+        assert_eq!(queue.spare_capacity(), 2);
+        queue.push(String::from("b"));
+        queue.pop();
+        assert_eq!(queue.spare_capacity(), 2);
+        queue.shrink_to_fit();
+        assert_eq!(queue.spare_capacity(), 0);
+        assert_eq!(queue.pop().as_deref(), Some("b"));
+        assert_eq!(queue.spare_capacity(), 0);
+    }
 }
@@ -1,7 +1,12 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
 use std::mem;
 
 pub struct List<T> {
     head: Link<T>,
+    len: usize,
 }
 
 enum Link<T> {
@@ -16,14 +21,18 @@ struct Node<T> {
 
 impl<T> List<T> {
     pub fn new() -> Self {
-        List { head: Link::Empty }
+        List {
+            head: Link::Empty,
+            len: 0,
+        }
     }
 
     pub fn push(&mut self, elem: T) {
         self.head = Link::More(Box::new(Node {
-            elem: elem,
+            elem,
             next: mem::replace(&mut self.head, Link::Empty),
-        }))
+        }));
+        self.len += 1;
     }
 
     pub fn pop(&mut self) -> Option<T> {
@@ -31,6 +40,7 @@ impl<T> List<T> {
             Link::Empty => None,
             Link::More(node) => {
                 self.head = node.next;
+                self.len -= 1;
                 Some(node.elem)
             }
         }
@@ -43,14 +53,142 @@ impl<T> List<T> {
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[deprecated(note = "use `len` instead")]
     pub fn size(&self) -> i32 {
-        let mut result = 0;
-        let mut curr = &self.head;
-        while let Link::More(node) = curr {
-            curr = &node.next;
-            result += 1;
+        self.len as i32
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: &self.head }
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: &'a Link<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next {
+            Link::Empty => None,
+            Link::More(node) => {
+                self.next = &node.next;
+                Some(&node.elem)
+            }
+        }
+    }
+}
+
+// Like std's `LinkedList`, extending and collecting keep the iteration
+// order, so new elements go below the existing ones.
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut link = &mut self.head;
+        while let Link::More(node) = link {
+            link = &mut node.next;
+        }
+
+        for elem in iter {
+            *link = Link::More(Box::new(Node {
+                elem,
+                next: Link::Empty,
+            }));
+            if let Link::More(node) = link {
+                link = &mut node.next;
+            }
+            self.len += 1;
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy> Extend<&'a T> for List<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for elem in self {
+            elem.hash(state);
         }
-        result
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -67,12 +205,13 @@ impl<T> Drop for List<T> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::hash_map::DefaultHasher;
 
     #[test]
     fn test_empty_on_creation() {
         let list: List<i32> = List::new();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
     }
 
     #[test]
@@ -82,14 +221,14 @@ mod test {
         list.push(2);
         list.push(3);
         list.push(4);
-        assert_eq!(list.size(), 4);
+        assert_eq!(list.len(), 4);
         list.pop();
-        assert_eq!(list.size(), 3);
+        assert_eq!(list.len(), 3);
         list.pop();
         list.pop();
         list.pop();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
     }
 
     #[test]
@@ -110,4 +249,55 @@ mod test {
         assert_eq!(list.pop(), None);
         assert!(list.is_empty());
     }
+
+    #[test]
+    fn test_collect_extend() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.len(), 3);
+        list.extend(vec![4, 5]);
+        list.extend(&[6]);
+        list.push(0);
+        let mut elems = Vec::new();
+        for &elem in &list {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            list.into_iter().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+        assert_eq!(list, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let shorter: List<i32> = vec![1, 2].into_iter().collect();
+        let bigger: List<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_size_alias() {
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        assert_eq!(list.size(), 2);
+    }
 }
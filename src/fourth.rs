@@ -1,9 +1,14 @@
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
 use std::rc::Rc;
 
 pub struct List<T> {
     head: Link<T>,
     tail: Link<T>,
+    len: usize,
 }
 
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
@@ -17,7 +22,7 @@ struct Node<T> {
 impl<T> Node<T> {
     fn new(elem: T) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Node {
-            elem: elem,
+            elem,
             next: None,
             prev: None,
         }))
@@ -29,6 +34,7 @@ impl<T> List<T> {
         List {
             head: None,
             tail: None,
+            len: 0,
         }
     }
 
@@ -45,6 +51,7 @@ impl<T> List<T> {
                 self.head = Some(new_head);
             }
         }
+        self.len += 1;
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
@@ -58,6 +65,7 @@ impl<T> List<T> {
                     self.tail.take();
                 }
             }
+            self.len -= 1;
             Rc::try_unwrap(node).ok().unwrap().into_inner().elem
         })
     }
@@ -75,6 +83,7 @@ impl<T> List<T> {
                 self.tail = Some(new_tail);
             }
         }
+        self.len += 1;
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
@@ -88,9 +97,170 @@ impl<T> List<T> {
                     self.head.take();
                 }
             }
+            self.len -= 1;
             Rc::try_unwrap(node).ok().unwrap().into_inner().elem
         })
     }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    fn nodes(&self) -> Nodes<T> {
+        Nodes(self.head.clone())
+    }
+
+    fn compare_by<F>(&self, other: &Self, mut compare: F) -> Option<Ordering>
+    where
+        F: FnMut(&T, &T) -> Option<Ordering>,
+    {
+        let (mut nodes, mut other_nodes) = (self.nodes(), other.nodes());
+        loop {
+            match (nodes.next(), other_nodes.next()) {
+                (Some(node), Some(other_node)) => {
+                    match compare(&node.borrow().elem, &other_node.borrow().elem) {
+                        Some(Ordering::Equal) => {}
+                        result => return result,
+                    }
+                }
+                (Some(_), None) => return Some(Ordering::Greater),
+                (None, Some(_)) => return Some(Ordering::Less),
+                (None, None) => return Some(Ordering::Equal),
+            }
+        }
+    }
+}
+
+// Elements can only be reached through a `RefCell` borrow, so the trait
+// impls below walk the nodes themselves instead of borrowing iterators.
+struct Nodes<T>(Link<T>);
+
+impl<T> Iterator for Nodes<T> {
+    type Item = Rc<RefCell<Node<T>>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.take().inspect(|node| {
+            self.0 = node.borrow().next.clone();
+        })
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy> Extend<&'a T> for List<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        let mut list = List::new();
+        for node in self.nodes() {
+            list.push_back(node.borrow().elem.clone());
+        }
+        list
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        for node in self.nodes() {
+            list.entry(&node.borrow().elem);
+        }
+        list.finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len != other.len {
+            return false;
+        }
+
+        let (mut nodes, mut other_nodes) = (self.nodes(), other.nodes());
+        loop {
+            match (nodes.next(), other_nodes.next()) {
+                (Some(node), Some(other_node)) => {
+                    if node.borrow().elem != other_node.borrow().elem {
+                        return false;
+                    }
+                }
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.compare_by(other, T::partial_cmp)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.compare_by(other, |a, b| Some(a.cmp(b))).unwrap()
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for node in self.nodes() {
+            node.borrow().elem.hash(state);
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> Drop for List<T> {
@@ -102,6 +272,7 @@ impl<T> Drop for List<T> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::hash_map::DefaultHasher;
     use testdrop::TestDrop;
 
     #[test]
@@ -170,4 +341,68 @@ mod test {
         drop(list);
         assert_eq!(6, testdrop.num_dropped_items());
     }
+
+    #[test]
+    fn test_collect_extend() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        list.extend(vec![4, 5]);
+        list.extend(&[6]);
+        list.push_front(0);
+        let mut elems = Vec::new();
+        for elem in list {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.into_iter().rev().collect::<Vec<_>>(), vec![3, 2, 1]);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        let mut copy = list.clone();
+        assert_eq!(list, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let shorter: List<i32> = vec![1, 2].into_iter().collect();
+        let bigger: List<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+
+        // The clone owns separate nodes, so popping from it leaves the original alone
+        copy.pop_back();
+        assert!(copy < list);
+        drop(list);
+        assert_eq!(copy.pop_back(), Some(2));
+    }
+
+    #[test]
+    fn test_len() {
+        let mut list = List::new();
+        assert!(list.is_empty());
+        list.push_front(1);
+        list.push_back(2);
+        list.extend(vec![3, 4]);
+        assert_eq!(list.len(), 4);
+        list.pop_back();
+        list.pop_front();
+        assert_eq!(list.len(), 2);
+        list.pop_front();
+        list.pop_front();
+        list.pop_front();
+        assert_eq!(list.len(), 0);
+        assert!(list.is_empty());
+    }
 }
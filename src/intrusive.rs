@@ -0,0 +1,409 @@
+use std::cell::Cell;
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::{PhantomData, PhantomPinned};
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Embedded in an element once per list it can belong to. `owner` is the id of
+// the list the element is currently linked into, or 0, and `next`/`prev`
+// point at neighbouring elements of that list. Everything sits in `Cell`s so
+// lists can relink elements they only borrow shared.
+pub struct Links<T> {
+    owner: Cell<usize>,
+    next: Cell<Option<NonNull<T>>>,
+    prev: Cell<Option<NonNull<T>>>,
+    _pin: PhantomPinned,
+}
+
+impl<T> Links<T> {
+    pub const fn new() -> Self {
+        Links {
+            owner: Cell::new(0),
+            next: Cell::new(None),
+            prev: Cell::new(None),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.owner.get() != 0
+    }
+}
+
+impl<T> Default for Links<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Links<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Links")
+            .field("linked", &self.is_linked())
+            .finish()
+    }
+}
+
+/// Maps an element to the `Links` a particular kind of list threads through.
+///
+/// # Safety
+///
+/// `links` must always return the same field of `elem`, and no other adapter
+/// may return that field. Lists follow the pointers stored there without
+/// checking them again.
+pub unsafe trait Adapter {
+    type Elem;
+    fn links(elem: &Self::Elem) -> &Links<Self::Elem>;
+}
+
+static NEXT_LIST_ID: AtomicUsize = AtomicUsize::new(1);
+
+// A doubly-linked list of elements it does not own. Elements are pinned and
+// borrowed for `'a`, so they cannot move or be dropped while any list might
+// point at them, and an element can sit in as many lists as it has `Links`,
+// one per adapter. Every list has a unique id it stamps into the links of its
+// elements, which lets `remove` reject elements that belong to another list.
+pub struct IntrusiveList<'a, A: Adapter> {
+    id: usize,
+    head: Option<NonNull<A::Elem>>,
+    tail: Option<NonNull<A::Elem>>,
+    len: usize,
+    marker: PhantomData<Pin<&'a A::Elem>>,
+}
+
+impl<'a, A: Adapter> IntrusiveList<'a, A> {
+    pub fn new() -> Self {
+        IntrusiveList {
+            id: NEXT_LIST_ID.fetch_add(1, Ordering::Relaxed),
+            head: None,
+            tail: None,
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn push_front(&mut self, elem: Pin<&'a A::Elem>) {
+        self.link_after(None, elem)
+    }
+
+    pub fn push_back(&mut self, elem: Pin<&'a A::Elem>) {
+        self.link_after(self.tail, elem)
+    }
+
+    // Inserts `elem` right after `at`, which must be linked into this list.
+    pub fn insert_after(&mut self, at: Pin<&A::Elem>, elem: Pin<&'a A::Elem>) {
+        assert!(self.contains(&at), "insertion point is not in this list");
+        self.link_after(Some(NonNull::from(at.get_ref())), elem)
+    }
+
+    pub fn pop_front(&mut self) -> Option<Pin<&'a A::Elem>> {
+        self.head.map(|node| unsafe { self.unlink(node) })
+    }
+
+    pub fn pop_back(&mut self) -> Option<Pin<&'a A::Elem>> {
+        self.tail.map(|node| unsafe { self.unlink(node) })
+    }
+
+    pub fn front(&self) -> Option<Pin<&'a A::Elem>> {
+        self.head.map(|node| unsafe { Self::pin(node) })
+    }
+
+    pub fn back(&self) -> Option<Pin<&'a A::Elem>> {
+        self.tail.map(|node| unsafe { Self::pin(node) })
+    }
+
+    pub fn contains(&self, elem: &A::Elem) -> bool {
+        A::links(elem).owner.get() == self.id
+    }
+
+    // Unlinks `elem` in O(1) wherever it sits in this list. Returns `false`
+    // if it is not linked into this list.
+    pub fn remove(&mut self, elem: Pin<&A::Elem>) -> bool {
+        if !self.contains(&elem) {
+            return false;
+        }
+        unsafe { self.unlink(NonNull::from(elem.get_ref())) };
+        true
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn iter(&self) -> Iter<'_, 'a, A> {
+        Iter {
+            head: self.head,
+            tail: self.tail,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    // Every pointer a list stores came from a `Pin<&'a A::Elem>`.
+    unsafe fn pin(node: NonNull<A::Elem>) -> Pin<&'a A::Elem> {
+        Pin::new_unchecked(&*node.as_ptr())
+    }
+
+    fn link_after(&mut self, prev: Option<NonNull<A::Elem>>, elem: Pin<&'a A::Elem>) {
+        let links = A::links(&elem);
+        assert!(!links.is_linked(), "element is already linked into a list");
+
+        let node = NonNull::from(elem.get_ref());
+        let next = match prev {
+            Some(prev) => unsafe { A::links(prev.as_ref()).next.get() },
+            None => self.head,
+        };
+
+        links.owner.set(self.id);
+        links.prev.set(prev);
+        links.next.set(next);
+
+        match prev {
+            Some(prev) => unsafe { A::links(prev.as_ref()).next.set(Some(node)) },
+            None => self.head = Some(node),
+        }
+        match next {
+            Some(next) => unsafe { A::links(next.as_ref()).prev.set(Some(node)) },
+            None => self.tail = Some(node),
+        }
+        self.len += 1;
+    }
+
+    // `node` must be linked into this list.
+    unsafe fn unlink(&mut self, node: NonNull<A::Elem>) -> Pin<&'a A::Elem> {
+        let elem = Self::pin(node);
+        let links = A::links(&elem);
+        let (prev, next) = (links.prev.take(), links.next.take());
+        links.owner.set(0);
+
+        match prev {
+            Some(prev) => A::links(prev.as_ref()).next.set(next),
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => A::links(next.as_ref()).prev.set(prev),
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+        elem
+    }
+}
+
+impl<'a, A: Adapter> Default for IntrusiveList<'a, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, A: Adapter> fmt::Debug for IntrusiveList<'a, A>
+where
+    A::Elem: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Leaves every element unlinked so it can join another list afterwards.
+impl<'a, A: Adapter> Drop for IntrusiveList<'a, A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<'l, 'a, A: Adapter> IntoIterator for &'l IntrusiveList<'a, A> {
+    type Item = Pin<&'a A::Elem>;
+    type IntoIter = Iter<'l, 'a, A>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Borrows the list so that nothing relinks the elements while it walks them,
+// but hands out the elements for as long as the list itself borrows them.
+pub struct Iter<'l, 'a, A: Adapter> {
+    head: Option<NonNull<A::Elem>>,
+    tail: Option<NonNull<A::Elem>>,
+    len: usize,
+    marker: PhantomData<&'l IntrusiveList<'a, A>>,
+}
+
+impl<'l, 'a, A: Adapter> Iterator for Iter<'l, 'a, A> {
+    type Item = Pin<&'a A::Elem>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.head.map(|node| unsafe {
+            let elem = IntrusiveList::<A>::pin(node);
+            self.len -= 1;
+            self.head = A::links(&elem).next.get();
+            elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'l, 'a, A: Adapter> DoubleEndedIterator for Iter<'l, 'a, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.tail.map(|node| unsafe {
+            let elem = IntrusiveList::<A>::pin(node);
+            self.len -= 1;
+            self.tail = A::links(&elem).prev.get();
+            elem
+        })
+    }
+}
+
+impl<'l, 'a, A: Adapter> ExactSizeIterator for Iter<'l, 'a, A> {}
+
+impl<'l, 'a, A: Adapter> FusedIterator for Iter<'l, 'a, A> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Task {
+        id: u32,
+        ready: Links<Task>,
+        timer: Links<Task>,
+    }
+
+    impl Task {
+        fn new(id: u32) -> Pin<Box<Task>> {
+            Box::pin(Task {
+                id,
+                ready: Links::new(),
+                timer: Links::new(),
+            })
+        }
+    }
+
+    struct Ready;
+
+    unsafe impl Adapter for Ready {
+        type Elem = Task;
+        fn links(task: &Task) -> &Links<Task> {
+            &task.ready
+        }
+    }
+
+    struct Timer;
+
+    unsafe impl Adapter for Timer {
+        type Elem = Task;
+        fn links(task: &Task) -> &Links<Task> {
+            &task.timer
+        }
+    }
+
+    fn ids<A: Adapter<Elem = Task>>(list: &IntrusiveList<'_, A>) -> Vec<u32> {
+        list.iter().map(|task| task.id).collect()
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let tasks: Vec<_> = (0..3).map(Task::new).collect();
+        let mut list = IntrusiveList::<Ready>::new();
+        list.push_back(tasks[1].as_ref());
+        list.push_back(tasks[2].as_ref());
+        list.push_front(tasks[0].as_ref());
+        assert_eq!(list.len(), 3);
+        assert_eq!(ids(&list), vec![0, 1, 2]);
+        assert_eq!(list.front().map(|task| task.id), Some(0));
+        assert_eq!(list.back().map(|task| task.id), Some(2));
+        assert_eq!(
+            list.iter().rev().map(|task| task.id).collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+
+        assert_eq!(list.pop_back().map(|task| task.id), Some(2));
+        assert!(!tasks[2].ready.is_linked());
+        assert_eq!(list.pop_front().map(|task| task.id), Some(0));
+        assert_eq!(list.pop_front().map(|task| task.id), Some(1));
+        assert_eq!(list.pop_front().map(|task| task.id), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_multiple_lists() {
+        let tasks: Vec<_> = (0..4).map(Task::new).collect();
+        let mut ready = IntrusiveList::<Ready>::new();
+        let mut timers = IntrusiveList::<Timer>::new();
+        for task in &tasks {
+            ready.push_back(task.as_ref());
+            timers.push_front(task.as_ref());
+        }
+
+        assert!(ready.remove(tasks[1].as_ref()));
+        assert!(!ready.remove(tasks[1].as_ref()));
+        assert!(timers.remove(tasks[3].as_ref()));
+        assert!(timers.remove(tasks[0].as_ref()));
+        assert_eq!(ids(&ready), vec![0, 2, 3]);
+        assert_eq!(ids(&timers), vec![2, 1]);
+        assert!(tasks[1].timer.is_linked());
+        assert!(!tasks[1].ready.is_linked());
+
+        timers.insert_after(tasks[2].as_ref(), tasks[3].as_ref());
+        assert_eq!(ids(&timers), vec![2, 3, 1]);
+        assert_eq!(timers.len(), 3);
+    }
+
+    #[test]
+    fn test_remove_from_other_list() {
+        let tasks: Vec<_> = (0..2).map(Task::new).collect();
+        let mut first = IntrusiveList::<Ready>::new();
+        let mut second = IntrusiveList::<Ready>::new();
+        first.push_back(tasks[0].as_ref());
+        second.push_back(tasks[1].as_ref());
+        assert!(!first.remove(tasks[1].as_ref()));
+        assert!(!second.contains(&tasks[0]));
+        assert_eq!(ids(&first), vec![0]);
+        assert_eq!(ids(&second), vec![1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already linked")]
+    fn test_push_linked() {
+        let task = Task::new(0);
+        let mut first = IntrusiveList::<Ready>::new();
+        let mut second = IntrusiveList::<Ready>::new();
+        first.push_back(task.as_ref());
+        second.push_back(task.as_ref());
+    }
+
+    #[test]
+    fn test_drop_unlinks() {
+        let tasks: Vec<_> = (0..2).map(Task::new).collect();
+        {
+            let mut list = IntrusiveList::<Ready>::new();
+            list.push_back(tasks[0].as_ref());
+            list.push_back(tasks[1].as_ref());
+            assert_eq!(format!("{:?}", tasks[0].ready), "Links { linked: true }");
+        }
+        assert!(!tasks[0].ready.is_linked());
+
+        let mut list = IntrusiveList::<Ready>::new();
+        list.push_back(tasks[1].as_ref());
+        list.push_back(tasks[0].as_ref());
+        assert_eq!(ids(&list), vec![1, 0]);
+    }
+}
@@ -1,6 +1,15 @@
+pub mod alloc;
+pub mod async_queue;
+pub mod concurrent;
 pub mod fifth;
 pub mod first;
 pub mod fourth;
+pub mod intrusive;
+pub mod persistent;
 pub mod second;
 pub mod sixth;
+pub mod skiplist;
+pub mod slab;
+pub mod sync;
 pub mod third;
+pub mod unrolled;
@@ -0,0 +1,733 @@
+use crate::third::{self, List};
+use std::cell::{Cell, OnceCell};
+use std::fmt;
+use std::iter::FromIterator;
+use std::rc::Rc;
+use std::vec;
+
+// Persistent queues built from pairs of `third::List`s. Every operation
+// returns a new version and leaves the old one usable; versions share
+// whatever nodes they have in common.
+
+// The batched queue: elements are popped from `front` and pushed onto
+// `back`, and when `front` runs out, `back` is reversed to become the new
+// `front`. Each element is reversed at most once, so a sequence of
+// operations on the newest version costs O(1) per operation amortised. An
+// old version whose reversal is pending pays for it again every time it is
+// used, which `RealTimeQueue` avoids.
+//
+// `front` is only empty when the whole queue is, so `peek` never reverses.
+pub struct Queue<T> {
+    front: List<T>,
+    back: List<T>,
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Queue {
+            front: List::new(),
+            back: List::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.front.iter().next()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self.front.iter(), &self.back)
+    }
+}
+
+impl<T: Clone> Queue<T> {
+    pub fn push_back(&self, elem: T) -> Self {
+        Queue::checked(self.front.clone(), self.back.append(elem))
+    }
+
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        let elem = self.peek()?.clone();
+        Some((elem, Queue::checked(self.front.tail(), self.back.clone())))
+    }
+
+    fn checked(front: List<T>, back: List<T>) -> Self {
+        if front.is_empty() {
+            Queue {
+                front: back.reverse(),
+                back: List::new(),
+            }
+        } else {
+            Queue { front, back }
+        }
+    }
+}
+
+// Okasaki's real-time queue. The reversal is not done in one go: when
+// `rear` grows one longer than `front`, `front` becomes a lazy stream that
+// appends the reversed `rear` one cell at a time, and `schedule` points at
+// the first cell that has not been computed yet. Every operation computes
+// one more cell, so by the time the next rotation starts the previous one
+// has finished and no single operation takes more than O(1) steps, on any
+// version. Cells are computed once and shared by every version holding
+// them.
+pub struct RealTimeQueue<T> {
+    front: Stream<T>,
+    rear: List<T>,
+    schedule: Stream<T>,
+    len: usize,
+}
+
+// A lazily computed list. A cell is either already computed or a pending
+// step of a rotation, which produces its element and the rest of the stream
+// the first time it is forced.
+struct Stream<T>(Option<Rc<StreamNode<T>>>);
+
+struct StreamNode<T> {
+    forced: OnceCell<(T, Stream<T>)>,
+    pending: Cell<Option<Rotation<T>>>,
+}
+
+// `front ++ reverse(rear) ++ acc`, where `rear` holds exactly one element
+// more than `front`.
+struct Rotation<T> {
+    front: Stream<T>,
+    rear: List<T>,
+    acc: Stream<T>,
+}
+
+#[cfg(test)]
+thread_local! {
+    static ROTATION_STEPS: Cell<usize> = const { Cell::new(0) };
+}
+
+impl<T> Stream<T> {
+    fn empty() -> Self {
+        Stream(None)
+    }
+
+    fn cons(elem: T, rest: Stream<T>) -> Self {
+        Stream(Some(Rc::new(StreamNode {
+            forced: OnceCell::from((elem, rest)),
+            pending: Cell::new(None),
+        })))
+    }
+
+    fn rotate(rotation: Rotation<T>) -> Self {
+        Stream(Some(Rc::new(StreamNode {
+            forced: OnceCell::new(),
+            pending: Cell::new(Some(rotation)),
+        })))
+    }
+}
+
+impl<T: Clone> Stream<T> {
+    fn force(&self) -> Option<(&T, &Stream<T>)> {
+        let node = self.0.as_deref()?;
+        let (elem, rest) = node.forced.get_or_init(|| {
+            let rotation = node.pending.take().expect("stream cell forced twice");
+            rotation.step()
+        });
+        Some((elem, rest))
+    }
+}
+
+impl<T: Clone> Rotation<T> {
+    fn step(self) -> (T, Stream<T>) {
+        #[cfg(test)]
+        ROTATION_STEPS.with(|steps| steps.set(steps.get() + 1));
+
+        let last = self.rear.iter().next().expect("rotating an empty rear");
+        match self.front.force() {
+            None => (last.clone(), self.acc),
+            Some((elem, rest)) => (
+                elem.clone(),
+                Stream::rotate(Rotation {
+                    front: rest.clone(),
+                    rear: self.rear.tail(),
+                    acc: Stream::cons(last.clone(), self.acc),
+                }),
+            ),
+        }
+    }
+}
+
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Stream(self.0.clone())
+    }
+}
+
+// Like `third::List`, walks the chain instead of recursing through it.
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(node) = next {
+            next = match Rc::try_unwrap(node) {
+                Ok(node) => node
+                    .forced
+                    .into_inner()
+                    .and_then(|(_, mut rest)| rest.0.take()),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl<T> RealTimeQueue<T> {
+    pub fn new() -> Self {
+        RealTimeQueue {
+            front: Stream::empty(),
+            rear: List::new(),
+            schedule: Stream::empty(),
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<T: Clone> RealTimeQueue<T> {
+    pub fn push_back(&self, elem: T) -> Self {
+        RealTimeQueue::exec(
+            self.front.clone(),
+            self.rear.append(elem),
+            &self.schedule,
+            self.len + 1,
+        )
+    }
+
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        let (elem, rest) = self.front.force()?;
+        let queue = RealTimeQueue::exec(
+            rest.clone(),
+            self.rear.clone(),
+            &self.schedule,
+            self.len - 1,
+        );
+        Some((elem.clone(), queue))
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.front.force().map(|(elem, _)| elem)
+    }
+
+    pub fn iter(&self) -> RealTimeIter<'_, T> {
+        RealTimeIter {
+            front: &self.front,
+            rear: reversed(&self.rear),
+        }
+    }
+
+    // Computes one cell of the schedule, or starts the next rotation once
+    // the schedule is used up, which happens when `rear` has grown one
+    // longer than `front`.
+    fn exec(front: Stream<T>, rear: List<T>, schedule: &Stream<T>, len: usize) -> Self {
+        match schedule.force() {
+            Some((_, rest)) => RealTimeQueue {
+                front,
+                rear,
+                schedule: rest.clone(),
+                len,
+            },
+            None => {
+                let front = Stream::rotate(Rotation {
+                    front,
+                    rear,
+                    acc: Stream::empty(),
+                });
+                RealTimeQueue {
+                    schedule: front.clone(),
+                    front,
+                    rear: List::new(),
+                    len,
+                }
+            }
+        }
+    }
+}
+
+// The banker's deque: like `Queue`, but either list can run out. When one
+// side is empty and the other has at least two elements, the other side is
+// split in half and one half reversed over, so both ends stay within reach
+// and each element is moved O(1) times amortised.
+pub struct Deque<T> {
+    front: List<T>,
+    back: List<T>,
+}
+
+impl<T> Deque<T> {
+    pub fn new() -> Self {
+        Deque {
+            front: List::new(),
+            back: List::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty() && self.back.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+
+    // With `front` empty, `back` holds at most the one element.
+    pub fn peek_front(&self) -> Option<&T> {
+        self.front.iter().next().or_else(|| self.back.iter().next())
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        self.back.iter().next().or_else(|| self.front.iter().next())
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self.front.iter(), &self.back)
+    }
+}
+
+impl<T: Clone> Deque<T> {
+    pub fn push_front(&self, elem: T) -> Self {
+        Deque::balanced(self.front.append(elem), self.back.clone())
+    }
+
+    pub fn push_back(&self, elem: T) -> Self {
+        Deque::balanced(self.front.clone(), self.back.append(elem))
+    }
+
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        if self.front.is_empty() {
+            let elem = self.back.iter().next()?.clone();
+            return Some((elem, Deque::new()));
+        }
+        let elem = self.front.iter().next()?.clone();
+        Some((elem, Deque::balanced(self.front.tail(), self.back.clone())))
+    }
+
+    pub fn pop_back(&self) -> Option<(T, Self)> {
+        if self.back.is_empty() {
+            let elem = self.front.iter().next()?.clone();
+            return Some((elem, Deque::new()));
+        }
+        let elem = self.back.iter().next()?.clone();
+        Some((elem, Deque::balanced(self.front.clone(), self.back.tail())))
+    }
+
+    fn balanced(front: List<T>, back: List<T>) -> Self {
+        let half = (front.len() + back.len()) / 2;
+        if front.is_empty() && back.len() >= 2 {
+            Deque {
+                front: back.drop(half).reverse(),
+                back: back.take(half),
+            }
+        } else if back.is_empty() && front.len() >= 2 {
+            Deque {
+                back: front.drop(half).reverse(),
+                front: front.take(half),
+            }
+        } else {
+            Deque { front, back }
+        }
+    }
+}
+
+// Front to back: the front list in order, then the back list reversed.
+pub struct Iter<'a, T> {
+    front: third::Iter<'a, T>,
+    back: vec::IntoIter<&'a T>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn new(front: third::Iter<'a, T>, back: &'a List<T>) -> Self {
+        Iter {
+            front,
+            back: reversed(back),
+        }
+    }
+}
+
+fn reversed<T>(list: &List<T>) -> vec::IntoIter<&T> {
+    let mut elems: Vec<_> = list.iter().collect();
+    elems.reverse();
+    elems.into_iter()
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.front.next().or_else(|| self.back.next())
+    }
+}
+
+// Forces the remaining cells of the front stream as it goes.
+pub struct RealTimeIter<'a, T> {
+    front: &'a Stream<T>,
+    rear: vec::IntoIter<&'a T>,
+}
+
+impl<'a, T: Clone> Iterator for RealTimeIter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.front.force() {
+            Some((elem, rest)) => {
+                self.front = rest;
+                Some(elem)
+            }
+            None => self.rear.next(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Queue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a RealTimeQueue<T> {
+    type Item = &'a T;
+    type IntoIter = RealTimeIter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Deque<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Extending replaces `self` with a new version pushed onto at the back.
+impl<T: Clone> Extend<T> for Queue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            *self = self.push_back(elem);
+        }
+    }
+}
+
+impl<T: Clone> Extend<T> for RealTimeQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            *self = self.push_back(elem);
+        }
+    }
+}
+
+impl<T: Clone> Extend<T> for Deque<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            *self = self.push_back(elem);
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for Queue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = Queue::new();
+        queue.extend(iter);
+        queue
+    }
+}
+
+impl<T: Clone> FromIterator<T> for RealTimeQueue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = RealTimeQueue::new();
+        queue.extend(iter);
+        queue
+    }
+}
+
+impl<T: Clone> FromIterator<T> for Deque<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut deque = Deque::new();
+        deque.extend(iter);
+        deque
+    }
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            front: self.front.clone(),
+            back: self.back.clone(),
+        }
+    }
+}
+
+impl<T> Clone for RealTimeQueue<T> {
+    fn clone(&self) -> Self {
+        RealTimeQueue {
+            front: self.front.clone(),
+            rear: self.rear.clone(),
+            schedule: self.schedule.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Clone for Deque<T> {
+    fn clone(&self) -> Self {
+        Deque {
+            front: self.front.clone(),
+            back: self.back.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Queue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for RealTimeQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Deque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Queue<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for Queue<T> {}
+
+impl<T: Clone + PartialEq> PartialEq for RealTimeQueue<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Clone + Eq> Eq for RealTimeQueue<T> {}
+
+impl<T: PartialEq> PartialEq for Deque<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for Deque<T> {}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Default for RealTimeQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Default for Deque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+    use testdrop::TestDrop;
+
+    fn drain<T: Clone>(mut queue: Queue<T>) -> Vec<T> {
+        let mut elems = Vec::new();
+        while let Some((elem, rest)) = queue.pop_front() {
+            elems.push(elem);
+            queue = rest;
+        }
+        elems
+    }
+
+    #[test]
+    fn test_queue() {
+        let empty = Queue::new();
+        assert!(empty.pop_front().is_none());
+        assert_eq!(empty.peek(), None);
+
+        let one = empty.push_back(1);
+        let three = one.push_back(2).push_back(3);
+        assert_eq!(three.peek(), Some(&1));
+        assert_eq!(three.len(), 3);
+        let (elem, two) = three.pop_front().unwrap();
+        assert_eq!(elem, 1);
+        let four = two.push_back(4);
+        assert_eq!(drain(four), vec![2, 3, 4]);
+
+        // Older versions are unaffected.
+        assert_eq!(drain(three.clone()), vec![1, 2, 3]);
+        assert_eq!(three.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(drain(one), vec![1]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_real_time_queue() {
+        let mut model = VecDeque::new();
+        let mut queue = RealTimeQueue::new();
+        let mut versions = Vec::new();
+        for i in 0..500 {
+            if i % 3 == 2 {
+                let popped = queue.pop_front().map(|(elem, rest)| {
+                    queue = rest;
+                    elem
+                });
+                assert_eq!(popped, model.pop_front());
+            } else {
+                queue = queue.push_back(i);
+                model.push_back(i);
+            }
+            assert_eq!(queue.len(), model.len());
+            assert_eq!(queue.peek(), model.front());
+            if i % 50 == 0 {
+                versions.push((queue.clone(), model.clone()));
+            }
+        }
+        for (queue, model) in versions {
+            assert!(queue.iter().eq(model.iter()));
+        }
+    }
+
+    // No operation, on the newest version or an old one, computes more than
+    // a couple of rotation steps.
+    #[test]
+    fn test_real_time_bounds() {
+        fn steps<R>(f: impl FnOnce() -> R) -> (R, usize) {
+            let before = ROTATION_STEPS.with(Cell::get);
+            let result = f();
+            (result, ROTATION_STEPS.with(Cell::get) - before)
+        }
+
+        let mut queue = RealTimeQueue::new();
+        let mut old = Vec::new();
+        for i in 0..1000 {
+            let (next, taken) = steps(|| queue.push_back(i));
+            assert!(taken <= 2);
+            queue = next;
+            if i % 100 == 0 {
+                old.push(queue.clone());
+            }
+        }
+        for version in old.iter().chain(Some(&queue)) {
+            let mut version = version.clone();
+            while let (Some((_, rest)), taken) = steps(|| version.pop_front()) {
+                assert!(taken <= 2);
+                version = rest;
+            }
+        }
+    }
+
+    #[test]
+    fn test_deque() {
+        let mut model = VecDeque::new();
+        let mut deque = Deque::new();
+        for i in 0..400 {
+            match i % 5 {
+                0 | 1 => {
+                    deque = deque.push_back(i);
+                    model.push_back(i);
+                }
+                2 => {
+                    deque = deque.push_front(i);
+                    model.push_front(i);
+                }
+                3 => {
+                    let popped = deque.pop_front().map(|(elem, rest)| {
+                        deque = rest;
+                        elem
+                    });
+                    assert_eq!(popped, model.pop_front());
+                }
+                _ => {
+                    let popped = deque.pop_back().map(|(elem, rest)| {
+                        deque = rest;
+                        elem
+                    });
+                    assert_eq!(popped, model.pop_back());
+                }
+            }
+            assert_eq!(deque.len(), model.len());
+            assert_eq!(deque.peek_front(), model.front());
+            assert_eq!(deque.peek_back(), model.back());
+        }
+        assert!(deque.iter().eq(model.iter()));
+
+        let small: Deque<i32> = vec![1, 2].into_iter().collect();
+        let (back, rest) = small.pop_back().unwrap();
+        assert_eq!(back, 2);
+        let (front, rest) = rest.pop_back().unwrap();
+        assert_eq!(front, 1);
+        assert!(rest.is_empty() && rest.pop_front().is_none());
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let queue = RealTimeQueue::new()
+            .push_back(Rc::new(testdrop.new_item()))
+            .push_back(Rc::new(testdrop.new_item()));
+        let (first, rest) = queue.pop_front().unwrap();
+        drop(queue);
+        drop(first);
+        assert_eq!(1, testdrop.num_dropped_items());
+        drop(rest);
+        assert_eq!(2, testdrop.num_dropped_items());
+    }
+
+    #[test]
+    fn test_long_drop() {
+        const LEN: usize = if cfg!(miri) { 1_000 } else { 100_000 };
+        let queue: RealTimeQueue<usize> = (0..LEN).collect();
+        let half = (0..LEN / 2).fold(queue.clone(), |q, _| q.pop_front().unwrap().1);
+        drop(queue);
+        assert_eq!(half.len(), LEN / 2);
+        let deque: Deque<usize> = (0..LEN).collect();
+        drop(deque);
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let queue: Queue<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(queue, queue.clone());
+        assert_eq!(format!("{:?}", queue), "[1, 2, 3]");
+        assert_ne!(queue, Queue::default());
+
+        let queue: RealTimeQueue<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(queue, queue.clone());
+        assert_eq!(format!("{:?}", queue), "[1, 2, 3]");
+        assert_ne!(queue, RealTimeQueue::default());
+
+        let deque: Deque<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(deque, deque.push_front(0).pop_front().unwrap().1);
+        assert_eq!(format!("{:?}", deque), "[1, 2, 3]");
+        assert_ne!(deque, Deque::default());
+    }
+}
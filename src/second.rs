@@ -1,8 +1,21 @@
-pub struct List<T> {
+use crate::alloc::{alloc_node, free_node, Global, NodeAlloc};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+pub struct List<T, A: NodeAlloc = Global> {
     head: Link<T>,
+    len: usize,
+    alloc: A,
+    marker: PhantomData<Box<Node<T>>>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+// Nodes are allocated through the list's `NodeAlloc` and owned by the list
+// as a whole.
+type Link<T> = Option<NonNull<Node<T>>>;
 
 struct Node<T> {
     elem: T,
@@ -11,19 +24,35 @@ struct Node<T> {
 
 impl<T> List<T> {
     pub fn new() -> Self {
-        List { head: None }
+        List::new_in(Global)
+    }
+}
+
+impl<T, A: NodeAlloc> List<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        List {
+            head: None,
+            len: 0,
+            alloc,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
     }
 
     pub fn push(&mut self, elem: T) {
-        self.head = Some(Box::new(Node {
-            elem: elem,
-            next: self.head.take(),
-        }))
+        let next = self.head;
+        self.head = Some(alloc_node(&self.alloc, Node { elem, next }));
+        self.len += 1;
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        self.head.take().map(|node| {
+        self.head.map(|node| {
+            let node = unsafe { free_node(&self.alloc, node) };
             self.head = node.next;
+            self.len -= 1;
             node.elem
         })
     }
@@ -32,44 +61,52 @@ impl<T> List<T> {
         self.head.is_none()
     }
 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[deprecated(note = "use `len` instead")]
     pub fn size(&self) -> i32 {
-        let mut result = 0;
-        let mut curr = &self.head;
-        while let Some(node) = curr {
-            curr = &node.next;
-            result += 1;
-        }
-        result
+        self.len as i32
     }
 
     pub fn peek(&self) -> Option<&T> {
-        self.head.as_ref().map(|node| &node.elem)
+        self.head.map(|node| unsafe { &(*node.as_ptr()).elem })
     }
 
     pub fn peek_mut(&mut self) -> Option<&mut T> {
-        self.head.as_mut().map(|node| &mut node.elem)
-    }
-
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter(self)
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).elem })
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            next: self.head.as_ref().map(|node| &**node),
+            next: self.head,
+            marker: PhantomData,
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
-            next: self.head.as_mut().map(|node| &mut **node),
+            next: self.head,
+            marker: PhantomData,
         }
     }
 }
 
-pub struct IntoIter<T>(List<T>);
+unsafe impl<T: Send, A: NodeAlloc + Send> Send for List<T, A> {}
+unsafe impl<T: Sync, A: NodeAlloc + Sync> Sync for List<T, A> {}
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A: NodeAlloc> IntoIterator for List<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T, A: NodeAlloc = Global>(List<T, A>);
+
+impl<T, A: NodeAlloc> Iterator for IntoIter<T, A> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
         self.0.pop()
@@ -77,52 +114,160 @@ impl<T> Iterator for IntoIter<T> {
 }
 
 pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+    next: Link<T>,
+    marker: PhantomData<&'a Node<T>>,
 }
 
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_ref().map(|node| &**node);
+        self.next.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            self.next = node.next;
             &node.elem
         })
     }
 }
 
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
 pub struct IterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
+    next: Link<T>,
+    marker: PhantomData<&'a mut Node<T>>,
 }
 
 impl<'a, T> Iterator for IterMut<'a, T> {
     type Item = &'a mut T;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.next.as_mut().map(|node| &mut **node);
+        self.next.map(|node| unsafe {
+            let node = &mut *node.as_ptr();
+            self.next = node.next;
             &mut node.elem
         })
     }
 }
 
-impl<T> Drop for List<T> {
-    fn drop(&mut self) {
-        let mut curr_link = self.head.take();
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+impl<'a, T, A: NodeAlloc> IntoIterator for &'a List<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: NodeAlloc> IntoIterator for &'a mut List<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+// Like std's `LinkedList`, extending and collecting keep the iteration
+// order, so new elements go below the existing ones.
+impl<T, A: NodeAlloc> Extend<T> for List<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut last = self.head;
+        while let Some(next) = last.and_then(|node| unsafe { (*node.as_ptr()).next }) {
+            last = Some(next);
+        }
+
+        for elem in iter {
+            let node = alloc_node(&self.alloc, Node { elem, next: None });
+            match last {
+                Some(last) => unsafe { (*last.as_ptr()).next = Some(node) },
+                None => self.head = Some(node),
+            }
+            last = Some(node);
+            self.len += 1;
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy, A: NodeAlloc> Extend<&'a T> for List<T, A> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T, A: NodeAlloc + Default> FromIterator<T> for List<T, A> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new_in(A::default());
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Clone, A: NodeAlloc + Clone> Clone for List<T, A> {
+    fn clone(&self) -> Self {
+        let mut list = List::new_in(self.alloc.clone());
+        list.extend(self.iter().cloned());
+        list
+    }
+}
+
+impl<T: fmt::Debug, A: NodeAlloc> fmt::Debug for List<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq, A: NodeAlloc> PartialEq for List<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, A: NodeAlloc> Eq for List<T, A> {}
+
+impl<T: PartialOrd, A: NodeAlloc> PartialOrd for List<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord, A: NodeAlloc> Ord for List<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
 
-        while let Some(mut boxed_link) = curr_link {
-            curr_link = boxed_link.next.take();
+impl<T: Hash, A: NodeAlloc> Hash for List<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for elem in self {
+            elem.hash(state);
         }
     }
 }
 
+impl<T, A: NodeAlloc + Default> Default for List<T, A> {
+    fn default() -> Self {
+        List::new_in(A::default())
+    }
+}
+
+impl<T, A: NodeAlloc> Drop for List<T, A> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::alloc::test::Counting;
+    use std::collections::hash_map::DefaultHasher;
 
     #[test]
     fn test_empty_on_creation() {
         let list: List<i32> = List::new();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
     }
 
     #[test]
@@ -132,14 +277,14 @@ mod test {
         list.push(2);
         list.push(3);
         list.push(4);
-        assert_eq!(list.size(), 4);
+        assert_eq!(list.len(), 4);
         list.pop();
-        assert_eq!(list.size(), 3);
+        assert_eq!(list.len(), 3);
         list.pop();
         list.pop();
         list.pop();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
     }
 
     #[test]
@@ -176,6 +321,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::option_map_unit_fn)]
     fn test_peek_mut() {
         let mut list = List::new();
         list.push(1);
@@ -232,4 +378,75 @@ mod test {
         assert_eq!(list.pop(), Some(4));
         assert_eq!(list.pop(), Some(2));
     }
+
+    #[test]
+    fn test_collect_extend() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.len(), 3);
+        list.extend(vec![4, 5]);
+        list.extend(&[6]);
+        list.push(0);
+        let mut elems = Vec::new();
+        for elem in &mut list {
+            elems.push(*elem);
+        }
+        assert_eq!(elems, vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            list.into_iter().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+        assert_eq!(list, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let shorter: List<i32> = vec![1, 2].into_iter().collect();
+        let bigger: List<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_size_alias() {
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        assert_eq!(list.size(), 2);
+    }
+
+    #[test]
+    fn test_node_alloc() {
+        let alloc = Counting::default();
+        let mut list = List::new_in(alloc.clone());
+        list.push(1);
+        list.push(2);
+        list.extend(vec![3, 4]);
+        assert_eq!(alloc.live.get(), 4);
+        assert_eq!(list.pop(), Some(2));
+        assert_eq!(alloc.live.get(), 3);
+
+        let copy = list.clone();
+        assert!(copy.allocator().can_free_from(&alloc));
+        assert_eq!(alloc.live.get(), 6);
+        drop(list);
+        assert_eq!(copy.into_iter().collect::<Vec<_>>(), vec![1, 3, 4]);
+        assert_eq!(alloc.live.get(), 0);
+        assert_eq!(alloc.total.get(), 7);
+    }
 }
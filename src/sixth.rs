@@ -1,199 +1,1053 @@
-use std::ptr;
+use crate::alloc::{alloc_node, free_node, Global, NodeAlloc};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::ptr::{self, NonNull};
 
-pub struct Queue<T> {
+pub struct Queue<T, A: NodeAlloc = Global> {
     head: Link<T>,
-    tail: *mut Node<T>,
+    tail: Link<T>,
+    len: usize,
+    alloc: A,
+    marker: PhantomData<Box<Node<T>>>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+// Every node is allocated through the queue's `NodeAlloc` and owned by the
+// queue as a whole; it is only freed again by `unlink`.
+type Link<T> = Option<NonNull<Node<T>>>;
 
 struct Node<T> {
     elem: T,
     next: Link<T>,
-    prev: *mut Node<T>,
+    prev: Link<T>,
 }
 
 impl<T> Queue<T> {
     pub fn new() -> Self {
+        Queue::new_in(Global)
+    }
+}
+
+impl<T, A: NodeAlloc> Queue<T, A> {
+    pub fn new_in(alloc: A) -> Self {
         Queue {
             head: None,
-            tail: ptr::null_mut(),
+            tail: None,
+            len: 0,
+            alloc,
+            marker: PhantomData,
         }
     }
 
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
     pub fn push_back(&mut self, elem: T) {
-        let mut new_tail = Box::new(Node {
-            elem: elem,
-            next: None,
-            prev: self.tail,
-        });
+        unsafe { self.link_after(self.tail, elem) };
+    }
 
-        let raw_tail: *mut Node<T> = new_tail.as_mut();
+    pub fn push_front(&mut self, elem: T) {
+        unsafe { self.link_after(None, elem) };
+    }
 
-        if self.tail.is_null() {
-            self.head = Some(new_tail);
-        } else {
-            unsafe {
-                (*self.tail).next = Some(new_tail);
-            }
-        }
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tail.map(|node| unsafe { self.unlink(node) })
+    }
 
-        self.tail = raw_tail;
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.map(|node| unsafe { self.unlink(node) })
     }
 
-    pub fn push_front(&mut self, elem: T) {
-        let mut new_head = Box::new(Node {
-            elem: elem,
-            next: None,
-            prev: ptr::null_mut(),
-        });
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[deprecated(note = "use `len` instead")]
+    pub fn size(&self) -> i32 {
+        self.len as i32
+    }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        self.head.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        self.tail.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+        self.tail.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn front_mut_or_insert_with<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
         if self.head.is_none() {
-            self.tail = new_head.as_mut();
-        } else {
-            self.head.take().map(|mut node| {
-                node.prev = new_head.as_mut();
-                new_head.next = Some(node);
-            });
+            self.push_front(f());
         }
+        self.peek_front_mut().unwrap()
+    }
 
-        self.head = Some(new_head);
+    pub fn back_mut_or_insert_with<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        if self.tail.is_none() {
+            self.push_back(f());
+        }
+        self.peek_back_mut().unwrap()
     }
 
-    pub fn pop_back(&mut self) -> Option<T> {
-        if self.tail.is_null() {
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            Some(unsafe { &(*self.node_at(index).as_ptr()).elem })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            Some(unsafe { &mut (*self.node_at(index).as_ptr()).elem })
+        } else {
             None
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, elem: T) {
+        assert!(
+            index <= self.len,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.len
+        );
+
+        let prev = match index {
+            0 => None,
+            _ => Some(self.node_at(index - 1)),
+        };
+        unsafe { self.link_after(prev, elem) };
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index < self.len {
+            let node = self.node_at(index);
+            Some(unsafe { self.unlink(node) })
         } else {
-            let mut result;
+            None
+        }
+    }
+
+    pub fn swap(&mut self, i: usize, j: usize) {
+        assert!(
+            i < self.len && j < self.len,
+            "swap indices (are {} and {}) should be < len (is {})",
+            i,
+            j,
+            self.len
+        );
+
+        let a = self.node_at(i);
+        let b = self.node_at(j);
+        unsafe {
+            ptr::swap(
+                ptr::addr_of_mut!((*a.as_ptr()).elem),
+                ptr::addr_of_mut!((*b.as_ptr()).elem),
+            )
+        }
+    }
+
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.sort_by(T::cmp)
+    }
+
+    pub fn sort_by_key<K: Ord, F: FnMut(&T) -> K>(&mut self, mut f: F) {
+        self.sort_by(|a, b| f(a).cmp(&f(b)))
+    }
+
+    pub fn sort_by<F: FnMut(&T, &T) -> Ordering>(&mut self, mut compare: F) {
+        let len = self.len;
+        let mut sorter = Sorter {
+            out_head: self.head,
+            out_tail: self.tail,
+            left: None,
+            left_len: 0,
+            rest: None,
+            queue: self,
+        };
+
+        let mut width = 1;
+        while width < len {
+            sorter.rest = sorter.out_head.take();
+            sorter.out_tail = None;
+
+            while let Some(start) = sorter.rest {
+                let mut right = Some(start);
+                while let (Some(node), true) = (right, sorter.left_len < width) {
+                    right = unsafe { (*node.as_ptr()).next };
+                    sorter.left_len += 1;
+                }
+                sorter.left = Some(start);
+                sorter.rest = right;
+
+                let mut right_len = width;
+                loop {
+                    let take_left = match (sorter.left_len > 0, right_len > 0) {
+                        (true, true) => match (sorter.left, sorter.rest) {
+                            (Some(left), Some(right)) => unsafe {
+                                compare(&(*left.as_ptr()).elem, &(*right.as_ptr()).elem)
+                                    != Ordering::Greater
+                            },
+                            _ => true,
+                        },
+                        (true, false) => true,
+                        (false, true) if sorter.rest.is_some() => false,
+                        _ => break,
+                    };
+
+                    if take_left {
+                        sorter.take_left();
+                    } else {
+                        sorter.take_right();
+                        right_len -= 1;
+                    }
+                }
+            }
+
+            width *= 2;
+        }
+    }
+
+    pub fn is_sorted(&self) -> bool
+    where
+        T: PartialOrd,
+    {
+        self.iter().zip(self.iter().skip(1)).all(|(a, b)| a <= b)
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.retain_mut(|elem| f(elem))
+    }
+
+    pub fn retain_mut<F: FnMut(&mut T) -> bool>(&mut self, mut f: F) {
+        for _ in self.extract_if(|elem| !f(elem)) {}
+    }
+
+    pub fn extract_if<F: FnMut(&mut T) -> bool>(&mut self, pred: F) -> ExtractIf<'_, T, F, A> {
+        ExtractIf {
+            next: self.head,
+            queue: self,
+            pred,
+        }
+    }
+
+    pub fn reverse(&mut self) {
+        let mut curr = self.head;
+        while let Some(node) = curr {
             unsafe {
-                result = self.tail.read();
+                let node = &mut *node.as_ptr();
+                mem::swap(&mut node.next, &mut node.prev);
+                curr = node.prev;
             }
+        }
+        mem::swap(&mut self.head, &mut self.tail);
+    }
+
+    pub fn rotate_left(&mut self, n: usize) {
+        assert!(
+            n <= self.len,
+            "rotation amount (is {}) should be <= len (is {})",
+            n,
+            self.len
+        );
+
+        if n == 0 || n == self.len {
+            return;
+        }
+
+        let new_head = self.node_at(n);
+        unsafe {
+            let new_tail = (*new_head.as_ptr()).prev.take().unwrap();
+            (*new_tail.as_ptr()).next = None;
+
+            let (old_head, old_tail) = (self.head.unwrap(), self.tail.unwrap());
+            (*old_tail.as_ptr()).next = Some(old_head);
+            (*old_head.as_ptr()).prev = Some(old_tail);
+
+            self.head = Some(new_head);
+            self.tail = Some(new_tail);
+        }
+    }
+
+    pub fn rotate_right(&mut self, n: usize) {
+        assert!(
+            n <= self.len,
+            "rotation amount (is {}) should be <= len (is {})",
+            n,
+            self.len
+        );
+
+        self.rotate_left(self.len - n);
+    }
+
+    pub fn append(&mut self, other: &mut Queue<T, A>) {
+        unsafe { self.splice_after_node(self.tail, other) }
+    }
+
+    pub fn prepend(&mut self, other: &mut Queue<T, A>) {
+        unsafe { self.splice_after_node(None, other) }
+    }
+
+    pub fn split_off(&mut self, at: usize) -> Queue<T, A>
+    where
+        A: Clone,
+    {
+        assert!(
+            at <= self.len,
+            "split index (is {}) should be <= len (is {})",
+            at,
+            self.len
+        );
+
+        if at == 0 {
+            let alloc = self.alloc.clone();
+            return mem::replace(self, Queue::new_in(alloc));
+        }
+
+        let node = self.node_at(at - 1);
+        unsafe { self.split_after_node(node, at - 1) }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            head: self.head,
+            tail: self.tail,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            head: self.head,
+            tail: self.tail,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn cursor_front(&self) -> Cursor<'_, T, A> {
+        Cursor {
+            curr: self.head,
+            index: 0,
+            queue: self,
+        }
+    }
+
+    pub fn cursor_back(&self) -> Cursor<'_, T, A> {
+        Cursor {
+            curr: self.tail,
+            index: self.len.saturating_sub(1),
+            queue: self,
+        }
+    }
+
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T, A> {
+        CursorMut {
+            curr: self.head,
+            index: 0,
+            queue: self,
+        }
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T, A> {
+        CursorMut {
+            curr: self.tail,
+            index: self.len.saturating_sub(1),
+            queue: self,
+        }
+    }
+
+    // Links a new node right after `prev`, or at the front when `prev` is `None`,
+    // and returns it. `prev` must be `None` or a node owned by this queue.
+    unsafe fn link_after(&mut self, prev: Link<T>, elem: T) -> NonNull<Node<T>> {
+        let next = match prev {
+            Some(prev) => (*prev.as_ptr()).next,
+            None => self.head,
+        };
+
+        let new_node = alloc_node(&self.alloc, Node { elem, next, prev });
+
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = Some(new_node),
+            None => self.head = Some(new_node),
+        }
+        match next {
+            Some(next) => (*next.as_ptr()).prev = Some(new_node),
+            None => self.tail = Some(new_node),
+        }
 
-            if (result.prev.is_null()) {
-                self.head = None;
+        self.len += 1;
+        new_node
+    }
+
+    // Detaches `node` from the chain, frees it and returns its element.
+    // `node` must be a node owned by this queue.
+    unsafe fn unlink(&mut self, node: NonNull<Node<T>>) -> T {
+        let node = free_node(&self.alloc, node);
+
+        match node.prev {
+            Some(prev) => (*prev.as_ptr()).next = node.next,
+            None => self.head = node.next,
+        }
+        match node.next {
+            Some(next) => (*next.as_ptr()).prev = node.prev,
+            None => self.tail = node.prev,
+        }
+
+        self.len -= 1;
+        node.elem
+    }
+
+    // Detaches every node after `node`, which sits at `index`, into a new queue
+    // sharing a clone of the allocator. `node` must be a node owned by this queue.
+    unsafe fn split_after_node(&mut self, node: NonNull<Node<T>>, index: usize) -> Queue<T, A>
+    where
+        A: Clone,
+    {
+        let mut split = Queue::new_in(self.alloc.clone());
+        if let Some(next) = (*node.as_ptr()).next.take() {
+            (*next.as_ptr()).prev = None;
+            split.head = Some(next);
+            split.tail = self.tail.replace(node);
+            split.len = mem::replace(&mut self.len, index + 1) - index - 1;
+        }
+        split
+    }
+
+    // Walks to the node at `index` from whichever end is closer.
+    // `index` must be less than the queue's length.
+    fn node_at(&self, index: usize) -> NonNull<Node<T>> {
+        unsafe {
+            if index < self.len / 2 {
+                let mut node = self.head.unwrap();
+                for _ in 0..index {
+                    node = (*node.as_ptr()).next.unwrap();
+                }
+                node
             } else {
-                unsafe {
-                    (*result.prev).next = None;
+                let mut node = self.tail.unwrap();
+                for _ in index + 1..self.len {
+                    node = (*node.as_ptr()).prev.unwrap();
                 }
+                node
             }
-            self.tail = result.prev;
-            Some(result.elem)
         }
     }
 
-    pub fn pop_front(&mut self) -> Option<T> {
-        let result = self.head.take().map(|node| {
+    // Moves every element of `other` right after `prev`, or to the front when `prev`
+    // is `None`. Nodes are relinked when our allocator can free them and moved into
+    // fresh nodes otherwise. `prev` must be `None` or a node owned by this queue.
+    unsafe fn splice_after_node(&mut self, prev: Link<T>, other: &mut Queue<T, A>) {
+        if !self.alloc.can_free_from(&other.alloc) {
+            let mut prev = prev;
+            while let Some(elem) = other.pop_front() {
+                prev = Some(self.link_after(prev, elem));
+            }
+            return;
+        }
+
+        let (other_head, other_tail) = match (other.head.take(), other.tail.take()) {
+            (Some(head), Some(tail)) => (head, tail),
+            _ => return,
+        };
+
+        let next = match prev {
+            Some(prev) => (*prev.as_ptr()).next,
+            None => self.head,
+        };
+
+        (*other_head.as_ptr()).prev = prev;
+        (*other_tail.as_ptr()).next = next;
+
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = Some(other_head),
+            None => self.head = Some(other_head),
+        }
+        match next {
+            Some(next) => (*next.as_ptr()).prev = Some(other_tail),
+            None => self.tail = Some(other_tail),
+        }
+
+        self.len += mem::replace(&mut other.len, 0);
+    }
+}
+
+// Same bounds as std's `LinkedList`: the queue owns its `T`s outright.
+unsafe impl<T: Send, A: NodeAlloc + Send> Send for Queue<T, A> {}
+unsafe impl<T: Sync, A: NodeAlloc + Sync> Sync for Queue<T, A> {}
+
+impl<T, A: NodeAlloc> IntoIterator for Queue<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T, A: NodeAlloc = Global>(Queue<T, A>);
+
+impl<T, A: NodeAlloc> Iterator for IntoIter<T, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T, A: NodeAlloc> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T, A: NodeAlloc> ExactSizeIterator for IntoIter<T, A> {}
+
+impl<T, A: NodeAlloc> FusedIterator for IntoIter<T, A> {}
+
+// The borrowing iterators count down the elements left between `head` and
+// `tail`, so the two ends never hand out the same node twice.
+pub struct Iter<'a, T> {
+    head: Link<T>,
+    tail: Link<T>,
+    len: usize,
+    marker: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.head.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            self.len -= 1;
+            self.head = node.next;
+            &node.elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.tail.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            self.len -= 1;
+            self.tail = node.prev;
+            &node.elem
+        })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
+pub struct IterMut<'a, T> {
+    head: Link<T>,
+    tail: Link<T>,
+    len: usize,
+    marker: PhantomData<&'a mut Node<T>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.head.map(|node| unsafe {
+            let node = &mut *node.as_ptr();
+            self.len -= 1;
             self.head = node.next;
+            &mut node.elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.tail.map(|node| unsafe {
+            let node = &mut *node.as_ptr();
+            self.len -= 1;
+            self.tail = node.prev;
+            &mut node.elem
+        })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+// Every node is unlinked before its element is handed out, and `next` is
+// advanced before `pred` runs, so the queue stays consistent even if the
+// predicate panics or the iterator is dropped early.
+pub struct ExtractIf<'a, T, F: FnMut(&mut T) -> bool, A: NodeAlloc = Global> {
+    queue: &'a mut Queue<T, A>,
+    next: Link<T>,
+    pred: F,
+}
 
-            if self.head.is_none() {
-                self.tail = ptr::null_mut();
+impl<'a, T, F: FnMut(&mut T) -> bool, A: NodeAlloc> Iterator for ExtractIf<'a, T, F, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.next {
+            unsafe {
+                self.next = (*node.as_ptr()).next;
+                if (self.pred)(&mut (*node.as_ptr()).elem) {
+                    return Some(self.queue.unlink(node));
+                }
             }
+        }
+        None
+    }
 
-            node.elem
-        });
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.queue.len))
+    }
+}
 
-        self.head.as_mut().map(|node| {
-            node.prev = ptr::null_mut();
-        });
+impl<'a, T, F: FnMut(&mut T) -> bool, A: NodeAlloc> FusedIterator for ExtractIf<'a, T, F, A> {}
 
-        result
+// A cursor always sits either on an element or on the "ghost" position that
+// lies between the tail and the head, where `curr` is `None` and `index` is
+// the queue's length.
+pub struct Cursor<'a, T, A: NodeAlloc = Global> {
+    curr: Link<T>,
+    index: usize,
+    queue: &'a Queue<T, A>,
+}
+
+impl<'a, T, A: NodeAlloc> Cursor<'a, T, A> {
+    pub fn index(&self) -> Option<usize> {
+        self.curr.map(|_| self.index)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.head.is_none()
+    pub fn move_next(&mut self) {
+        self.index = match self.curr {
+            Some(_) => self.index + 1,
+            None => 0,
+        };
+        self.curr = self.next_node();
+    }
+
+    pub fn move_prev(&mut self) {
+        self.index = self.index.checked_sub(1).unwrap_or(self.queue.len);
+        self.curr = self.prev_node();
+    }
+
+    pub fn current(&self) -> Option<&'a T> {
+        self.curr.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.next_node()
+            .map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        self.prev_node()
+            .map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    fn next_node(&self) -> Link<T> {
+        match self.curr {
+            Some(node) => unsafe { (*node.as_ptr()).next },
+            None => self.queue.head,
+        }
+    }
+
+    fn prev_node(&self) -> Link<T> {
+        match self.curr {
+            Some(node) => unsafe { (*node.as_ptr()).prev },
+            None => self.queue.tail,
+        }
+    }
+}
+
+unsafe impl<'a, T: Sync, A: NodeAlloc + Sync> Send for Cursor<'a, T, A> {}
+unsafe impl<'a, T: Sync, A: NodeAlloc + Sync> Sync for Cursor<'a, T, A> {}
+
+pub struct CursorMut<'a, T, A: NodeAlloc = Global> {
+    curr: Link<T>,
+    index: usize,
+    queue: &'a mut Queue<T, A>,
+}
+
+impl<'a, T, A: NodeAlloc> CursorMut<'a, T, A> {
+    pub fn index(&self) -> Option<usize> {
+        self.curr.map(|_| self.index)
+    }
+
+    pub fn move_next(&mut self) {
+        self.index = match self.curr {
+            Some(_) => self.index + 1,
+            None => 0,
+        };
+        self.curr = self.next_node();
+    }
+
+    pub fn move_prev(&mut self) {
+        self.index = self.index.checked_sub(1).unwrap_or(self.queue.len);
+        self.curr = self.prev_node();
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.curr.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        self.next_node()
+            .map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn peek_prev(&mut self) -> Option<&mut T> {
+        self.prev_node()
+            .map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn as_cursor(&self) -> Cursor<'_, T, A> {
+        Cursor {
+            curr: self.curr,
+            index: self.index,
+            queue: self.queue,
+        }
+    }
+
+    pub fn insert_after(&mut self, elem: T) {
+        unsafe { self.queue.link_after(self.curr, elem) };
+        if self.curr.is_none() {
+            self.index += 1;
+        }
+    }
+
+    pub fn insert_before(&mut self, elem: T) {
+        let prev = self.prev_node();
+        unsafe { self.queue.link_after(prev, elem) };
+        self.index += 1;
+    }
+
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.curr?;
+        self.curr = self.next_node();
+        Some(unsafe { self.queue.unlink(node) })
+    }
+
+    pub fn split_after(&mut self) -> Queue<T, A>
+    where
+        A: Clone,
+    {
+        let node = match self.curr {
+            Some(node) => node,
+            None => {
+                self.index = 0;
+                let alloc = self.queue.alloc.clone();
+                return mem::replace(self.queue, Queue::new_in(alloc));
+            }
+        };
+
+        unsafe { self.queue.split_after_node(node, self.index) }
+    }
+
+    pub fn split_before(&mut self) -> Queue<T, A>
+    where
+        A: Clone,
+    {
+        let alloc = self.queue.alloc.clone();
+        let node = match self.curr {
+            Some(node) => node,
+            None => {
+                self.index = 0;
+                return mem::replace(self.queue, Queue::new_in(alloc));
+            }
+        };
+
+        let mut split = Queue::new_in(alloc);
+        unsafe {
+            if let Some(prev) = (*node.as_ptr()).prev.take() {
+                (*prev.as_ptr()).next = None;
+                self.queue.len -= self.index;
+                split.head = self.queue.head.replace(node);
+                split.tail = Some(prev);
+                split.len = mem::replace(&mut self.index, 0);
+            }
+        }
+        split
+    }
+
+    pub fn splice_after(&mut self, mut other: Queue<T, A>) {
+        if self.curr.is_none() {
+            self.index += other.len;
+        }
+        unsafe { self.queue.splice_after_node(self.curr, &mut other) }
+    }
+
+    pub fn splice_before(&mut self, mut other: Queue<T, A>) {
+        self.index += other.len;
+        let prev = self.prev_node();
+        unsafe { self.queue.splice_after_node(prev, &mut other) }
+    }
+
+    fn next_node(&self) -> Link<T> {
+        match self.curr {
+            Some(node) => unsafe { (*node.as_ptr()).next },
+            None => self.queue.head,
+        }
+    }
+
+    fn prev_node(&self) -> Link<T> {
+        match self.curr {
+            Some(node) => unsafe { (*node.as_ptr()).prev },
+            None => self.queue.tail,
+        }
+    }
+}
+
+unsafe impl<'a, T: Send, A: NodeAlloc + Send> Send for CursorMut<'a, T, A> {}
+unsafe impl<'a, T: Sync, A: NodeAlloc + Sync> Sync for CursorMut<'a, T, A> {}
+
+// Merge sort state. The queue's nodes are always the merged `out` chain,
+// followed by the first `left_len` nodes from `left`, followed by the chain
+// starting at `rest`. Only the `next` links are kept up to date while
+// merging; dropping the sorter stitches the pieces back together and
+// rebuilds `prev` and `tail`, even if the comparison panicked.
+struct Sorter<'a, T, A: NodeAlloc> {
+    queue: &'a mut Queue<T, A>,
+    out_head: Link<T>,
+    out_tail: Link<T>,
+    left: Link<T>,
+    left_len: usize,
+    rest: Link<T>,
+}
+
+impl<'a, T, A: NodeAlloc> Sorter<'a, T, A> {
+    fn take_left(&mut self) {
+        let node = self.left.unwrap();
+        self.left = unsafe { (*node.as_ptr()).next };
+        self.left_len -= 1;
+        self.push_out(node);
+    }
+
+    fn take_right(&mut self) {
+        let node = self.rest.unwrap();
+        self.rest = unsafe { (*node.as_ptr()).next };
+        self.push_out(node);
+    }
+
+    fn push_out(&mut self, node: NonNull<Node<T>>) {
+        unsafe { (*node.as_ptr()).next = None };
+        match self.out_tail {
+            Some(tail) => unsafe { (*tail.as_ptr()).next = Some(node) },
+            None => self.out_head = Some(node),
+        }
+        self.out_tail = Some(node);
+    }
+}
+
+impl<'a, T, A: NodeAlloc> Drop for Sorter<'a, T, A> {
+    fn drop(&mut self) {
+        while self.left_len > 0 {
+            self.take_left();
+        }
+        while self.rest.is_some() {
+            self.take_right();
+        }
+
+        let mut prev = None;
+        let mut curr = self.out_head;
+        while let Some(node) = curr {
+            unsafe {
+                (*node.as_ptr()).prev = prev;
+                curr = (*node.as_ptr()).next;
+            }
+            prev = Some(node);
+        }
+
+        self.queue.head = self.out_head;
+        self.queue.tail = self.out_tail;
+    }
+}
+
+impl<'a, T, A: NodeAlloc> IntoIterator for &'a Queue<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: NodeAlloc> IntoIterator for &'a mut Queue<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
     }
+}
 
-    pub fn size(&self) -> i32 {
-        let mut result = 0;
-        let mut curr = &self.head;
-        while let Some(node) = curr {
-            curr = &node.next;
-            result += 1;
+impl<T, A: NodeAlloc> Extend<T> for Queue<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
         }
-        result
     }
+}
 
-    pub fn peek_front(&self) -> Option<&T> {
-        self.head.as_ref().map(|node| &node.elem)
+impl<'a, T: 'a + Copy, A: NodeAlloc> Extend<&'a T> for Queue<T, A> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
     }
+}
 
-    pub fn peek_front_mut(&mut self) -> Option<&mut T> {
-        self.head.as_mut().map(|node| &mut node.elem)
+impl<T, A: NodeAlloc + Default> FromIterator<T> for Queue<T, A> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = Queue::new_in(A::default());
+        queue.extend(iter);
+        queue
     }
+}
 
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter(self)
+impl<T: Clone, A: NodeAlloc + Clone> Clone for Queue<T, A> {
+    fn clone(&self) -> Self {
+        let mut queue = Queue::new_in(self.alloc.clone());
+        queue.extend(self.iter().cloned());
+        queue
     }
+}
 
-    pub fn iter(&self) -> Iter<T> {
-        Iter {
-            next: self.head.as_ref().map(|node| &**node),
-        }
+impl<T: fmt::Debug, A: NodeAlloc> fmt::Debug for Queue<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
     }
+}
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
-        IterMut {
-            next: self.head.as_mut().map(|node| &mut **node),
-        }
+impl<T: PartialEq, A: NodeAlloc> PartialEq for Queue<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
     }
 }
 
-pub struct IntoIter<T>(Queue<T>);
+impl<T: Eq, A: NodeAlloc> Eq for Queue<T, A> {}
 
-impl<T> Iterator for IntoIter<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.0.pop_front()
+impl<T: PartialOrd, A: NodeAlloc> PartialOrd for Queue<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
     }
 }
 
-pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+impl<T: Ord, A: NodeAlloc> Ord for Queue<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_ref().map(|node| &**node);
-            &node.elem
-        })
+impl<T: Hash, A: NodeAlloc> Hash for Queue<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
     }
 }
 
-pub struct IterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
+impl<T, A: NodeAlloc> Index<usize> for Queue<T, A> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        let len = self.len;
+        self.get(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
+        })
+    }
 }
 
-impl<'a, T> Iterator for IterMut<'a, T> {
-    type Item = &'a mut T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.next.as_mut().map(|node| &mut **node);
-            &mut node.elem
+impl<T, A: NodeAlloc> IndexMut<usize> for Queue<T, A> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let len = self.len;
+        self.get_mut(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
         })
     }
 }
 
-impl<T> Drop for Queue<T> {
-    fn drop(&mut self) {
-        let mut curr_link = self.head.take();
+impl<T, A: NodeAlloc + Default> Default for Queue<T, A> {
+    fn default() -> Self {
+        Queue::new_in(A::default())
+    }
+}
 
-        while let Some(mut boxed_link) = curr_link {
-            curr_link = boxed_link.next.take();
-        }
+impl<T, A: NodeAlloc> Drop for Queue<T, A> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::alloc::test::Counting;
+    use std::collections::hash_map::DefaultHasher;
+    use testdrop::TestDrop;
 
     #[test]
     fn test_empty_on_creation() {
         let queue: Queue<i32> = Queue::new();
         assert!(queue.is_empty());
-        assert_eq!(queue.size(), 0);
+        assert_eq!(queue.len(), 0);
     }
 
     #[test]
@@ -203,14 +1057,14 @@ mod test {
         queue.push_back(2);
         queue.push_back(3);
         queue.push_back(4);
-        assert_eq!(queue.size(), 4);
+        assert_eq!(queue.len(), 4);
         queue.pop_front();
-        assert_eq!(queue.size(), 3);
+        assert_eq!(queue.len(), 3);
         queue.pop_front();
         queue.pop_front();
         queue.pop_front();
         assert!(queue.is_empty());
-        assert_eq!(queue.size(), 0);
+        assert_eq!(queue.len(), 0);
     }
 
     #[test]
@@ -325,8 +1179,9 @@ mod test {
         let mut queue = Queue::new();
         queue.push_back(1);
         queue.push_back(2);
-        let result = queue.peek_front_mut();
-        result.map(|val| *val = 3);
+        if let Some(val) = queue.peek_front_mut() {
+            *val = 3;
+        }
         assert_eq!(queue.pop_front(), Some(3));
         assert_eq!(queue.pop_front(), Some(2));
         assert_eq!(queue.pop_front(), None);
@@ -377,4 +1232,825 @@ mod test {
         assert_eq!(queue.pop_front(), Some(4));
         assert_eq!(queue.pop_front(), Some(2));
     }
+
+    fn from_vec(elems: Vec<i32>) -> Queue<i32> {
+        let mut queue = Queue::new();
+        for elem in elems {
+            queue.push_back(elem);
+        }
+        queue
+    }
+
+    fn to_vec<A: NodeAlloc>(queue: Queue<i32, A>) -> Vec<i32> {
+        queue.into_iter().collect()
+    }
+
+    #[test]
+    fn test_cursor_move() {
+        let queue = from_vec(vec![1, 2, 3]);
+        let mut cursor = queue.cursor_front();
+        assert_eq!(cursor.current(), Some(&1));
+        assert_eq!(cursor.peek_prev(), None);
+        assert_eq!(cursor.peek_next(), Some(&2));
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_next();
+        // Ghost position between tail and head
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&1));
+        assert_eq!(cursor.peek_prev(), Some(&3));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&1));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&3));
+
+        let empty: Queue<i32> = Queue::new();
+        let mut cursor = empty.cursor_back();
+        assert_eq!(cursor.current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+    }
+
+    #[test]
+    fn test_cursor_mut_insert() {
+        let mut queue = from_vec(vec![2, 4]);
+        let mut cursor = queue.cursor_front_mut();
+        cursor.insert_before(1);
+        cursor.insert_after(3);
+        cursor.move_prev();
+        cursor.move_prev();
+        // On the ghost, insert_after pushes to the front and insert_before to the back
+        assert_eq!(cursor.current(), None);
+        cursor.insert_after(0);
+        cursor.insert_before(5);
+        if let Some(val) = cursor.peek_prev() {
+            *val *= 10;
+        }
+        assert_eq!(queue.peek_front(), Some(&0));
+        assert_eq!(to_vec(queue), vec![0, 1, 2, 3, 4, 50]);
+
+        let mut queue = Queue::new();
+        queue.cursor_back_mut().insert_before(1);
+        queue.cursor_back_mut().insert_after(2);
+        queue.push_back(3);
+        assert_eq!(queue.pop_back(), Some(3));
+        assert_eq!(to_vec(queue), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_cursor_mut_remove() {
+        let mut queue = from_vec(vec![1, 2, 3, 4]);
+        let mut cursor = queue.cursor_front_mut();
+        assert_eq!(cursor.remove_current(), Some(1));
+        assert_eq!(cursor.current(), Some(&mut 2));
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(4));
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.remove_current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&mut 3));
+        queue.push_back(5);
+        queue.push_front(0);
+        assert_eq!(to_vec(queue), vec![0, 2, 3, 5]);
+
+        let mut queue = from_vec(vec![1]);
+        assert_eq!(queue.cursor_back_mut().remove_current(), Some(1));
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop_back(), None);
+        queue.push_front(2);
+        assert_eq!(to_vec(queue), vec![2]);
+    }
+
+    #[test]
+    fn test_cursor_mut_split() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5]);
+        let mut cursor = queue.cursor_front_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let front = cursor.split_before();
+        let back = cursor.split_after();
+        assert_eq!(cursor.current(), Some(&mut 3));
+        assert_eq!(to_vec(front), vec![1, 2]);
+        assert_eq!(to_vec(back), vec![4, 5]);
+        queue.push_front(2);
+        queue.push_back(4);
+        assert_eq!(to_vec(queue), vec![2, 3, 4]);
+
+        let mut queue = from_vec(vec![1, 2]);
+        assert!(queue.cursor_front_mut().split_before().is_empty());
+        assert!(queue.cursor_back_mut().split_after().is_empty());
+        let mut cursor = queue.cursor_back_mut();
+        cursor.move_next();
+        let all = cursor.split_after();
+        assert!(queue.is_empty());
+        assert_eq!(to_vec(all), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_cursor_mut_splice() {
+        let mut queue = from_vec(vec![1, 5]);
+        let mut cursor = queue.cursor_front_mut();
+        cursor.splice_after(from_vec(vec![2, 3]));
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.splice_before(from_vec(vec![4]));
+        cursor.splice_before(Queue::new());
+        cursor.move_next();
+        cursor.splice_after(from_vec(vec![0]));
+        cursor.splice_before(from_vec(vec![6]));
+        assert_eq!(queue.pop_back(), Some(6));
+        assert_eq!(queue.pop_front(), Some(0));
+        assert_eq!(to_vec(queue), vec![1, 2, 3, 4, 5]);
+
+        let mut queue = Queue::new();
+        queue.cursor_front_mut().splice_after(from_vec(vec![1, 2]));
+        queue.push_back(3);
+        assert_eq!(to_vec(queue), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_heap_elements() {
+        let mut queue = Queue::new();
+        queue.push_back(String::from("b"));
+        queue.push_front(String::from("a"));
+        queue.push_back(String::from("c"));
+        assert_eq!(queue.pop_back(), Some(String::from("c")));
+        assert_eq!(queue.pop_back(), Some(String::from("b")));
+        queue.push_back(String::from("d"));
+        assert_eq!(queue.pop_front(), Some(String::from("a")));
+        assert_eq!(queue.pop_back(), Some(String::from("d")));
+        assert_eq!(queue.pop_back(), None);
+        queue.push_front(String::from("e"));
+        assert_eq!(queue.peek_front().map(String::as_str), Some("e"));
+    }
+
+    #[test]
+    fn test_push_pop_drops() {
+        for &(push_front, pop_front) in
+            [(true, true), (true, false), (false, true), (false, false)].iter()
+        {
+            let testdrop = TestDrop::new();
+            let mut queue = Queue::new();
+            for _ in 0..4 {
+                if push_front {
+                    queue.push_front(testdrop.new_item());
+                } else {
+                    queue.push_back(testdrop.new_item());
+                }
+            }
+
+            let pop = |queue: &mut Queue<_>| {
+                if pop_front {
+                    queue.pop_front()
+                } else {
+                    queue.pop_back()
+                }
+            };
+
+            let (id, item) = pop(&mut queue).unwrap();
+            testdrop.assert_no_drop(id);
+            drop(item);
+            testdrop.assert_drop(id);
+            pop(&mut queue);
+            assert_eq!(testdrop.num_dropped_items(), 2);
+
+            queue.push_front(testdrop.new_item());
+            queue.push_back(testdrop.new_item());
+            assert_eq!(testdrop.num_dropped_items(), 2);
+            drop(queue);
+            assert_eq!(testdrop.num_tracked_items(), 6);
+            assert_eq!(testdrop.num_dropped_items(), 6);
+        }
+    }
+
+    #[test]
+    fn test_cursor_drops() {
+        let testdrop = TestDrop::new();
+        let mut queue = Queue::new();
+        for _ in 0..6 {
+            queue.push_back(testdrop.new_item());
+        }
+
+        let mut cursor = queue.cursor_front_mut();
+        cursor.move_next();
+        cursor.remove_current();
+        assert_eq!(testdrop.num_dropped_items(), 1);
+        let front = cursor.split_before();
+        cursor.move_next();
+        let back = cursor.split_after();
+        cursor.splice_after(front);
+        assert_eq!(testdrop.num_dropped_items(), 1);
+        drop(back);
+        assert_eq!(testdrop.num_dropped_items(), 3);
+        drop(queue);
+        assert_eq!(testdrop.num_dropped_items(), 6);
+    }
+
+    #[test]
+    fn test_iter_double_ended() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5]);
+        let mut iter = queue.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&5));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.len(), 0);
+
+        assert_eq!(
+            queue.iter().rev().copied().collect::<Vec<_>>(),
+            vec![5, 4, 3, 2, 1]
+        );
+        assert_eq!(queue.iter().rposition(|&val| val == 2), Some(1));
+        assert!(queue
+            .iter()
+            .zip(queue.iter().rev())
+            .all(|(a, b)| a + b == 6));
+
+        for val in queue.iter_mut().rev().take(2) {
+            *val *= 10;
+        }
+        assert_eq!(to_vec(queue), vec![1, 2, 3, 40, 50]);
+    }
+
+    #[test]
+    fn test_iter_mut_meets_in_middle() {
+        let mut queue = from_vec(vec![1, 2, 3]);
+        let mut iter = queue.iter_mut();
+        let front = iter.next().unwrap();
+        let back = iter.next_back().unwrap();
+        let middle = iter.next_back().unwrap();
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        *front += 10;
+        *middle += 20;
+        *back += 30;
+        assert_eq!(to_vec(queue), vec![11, 22, 33]);
+
+        let mut queue: Queue<i32> = Queue::new();
+        let mut iter = queue.iter_mut();
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_into_iter_double_ended() {
+        let testdrop = TestDrop::new();
+        let mut queue = Queue::new();
+        for _ in 0..4 {
+            queue.push_back(testdrop.new_item());
+        }
+        let mut iter = queue.into_iter();
+        assert_eq!(iter.len(), 4);
+        let (id, _) = iter.next_back().unwrap();
+        assert_eq!(id, 3);
+        let (id, _) = iter.next().unwrap();
+        assert_eq!(id, 0);
+        assert_eq!(iter.len(), 2);
+        drop(iter);
+        assert_eq!(testdrop.num_dropped_items(), 4);
+
+        let rev: Vec<_> = from_vec(vec![1, 2, 3]).into_iter().rev().collect();
+        assert_eq!(rev, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_cursor_index() {
+        let mut queue = from_vec(vec![1, 2, 3]);
+        let mut cursor = queue.cursor_back_mut();
+        assert_eq!(cursor.index(), Some(2));
+        cursor.move_next();
+        assert_eq!(cursor.index(), None);
+        cursor.insert_after(0);
+        cursor.move_prev();
+        assert_eq!(cursor.index(), Some(3));
+        cursor.splice_before(from_vec(vec![4, 5]));
+        assert_eq!(cursor.index(), Some(5));
+        let back = cursor.split_before();
+        assert_eq!(cursor.index(), Some(0));
+        assert_eq!(back.len(), 5);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.iter().len(), 1);
+    }
+
+    #[test]
+    fn test_peek_back() {
+        let mut queue = Queue::new();
+        assert_eq!(queue.peek_back(), None);
+        assert_eq!(queue.peek_back_mut(), None);
+        queue.push_back(1);
+        queue.push_back(2);
+        assert_eq!(queue.peek_back(), Some(&2));
+        if let Some(val) = queue.peek_back_mut() {
+            *val = 3;
+        }
+        assert_eq!(queue.pop_back(), Some(3));
+        assert_eq!(queue.peek_back(), Some(&1));
+        assert_eq!(queue.peek_front(), Some(&1));
+    }
+
+    #[test]
+    fn test_or_insert_with() {
+        let mut queue = Queue::new();
+        *queue.back_mut_or_insert_with(|| 1) += 10;
+        assert_eq!(queue.peek_back(), Some(&11));
+        *queue.back_mut_or_insert_with(|| panic!("queue is not empty")) += 10;
+        assert_eq!(queue.pop_back(), Some(21));
+
+        *queue.front_mut_or_insert_with(|| 5) *= 2;
+        queue.push_back(7);
+        *queue.front_mut_or_insert_with(|| 0) += 1;
+        assert_eq!(to_vec(queue), vec![11, 7]);
+    }
+
+    #[test]
+    fn test_append_prepend() {
+        let mut queue = from_vec(vec![3, 4]);
+        let mut other = from_vec(vec![5, 6]);
+        queue.append(&mut other);
+        assert!(other.is_empty());
+        other.push_back(2);
+        queue.prepend(&mut other);
+        assert!(other.is_empty());
+        queue.append(&mut other);
+        queue.prepend(&mut other);
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.peek_back(), Some(&6));
+        queue.push_front(1);
+        assert_eq!(to_vec(queue), vec![1, 2, 3, 4, 5, 6]);
+
+        let mut empty = Queue::new();
+        let mut single = from_vec(vec![1]);
+        empty.append(&mut single);
+        assert_eq!(empty.peek_front(), Some(&1));
+        assert_eq!(empty.peek_back(), Some(&1));
+        let mut single = from_vec(vec![0]);
+        single.append(&mut empty);
+        single.prepend(&mut Queue::new());
+        assert_eq!(single.pop_back(), Some(1));
+        assert_eq!(single.pop_back(), Some(0));
+        assert_eq!(single.pop_front(), None);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5, 6]);
+        let back = queue.split_off(4);
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.peek_back(), Some(&4));
+        assert_eq!(to_vec(back), vec![5, 6]);
+        let back = queue.split_off(1);
+        assert_eq!(to_vec(back), vec![2, 3, 4]);
+        assert!(queue.split_off(1).is_empty());
+        let all = queue.split_off(0);
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek_back(), None);
+        assert_eq!(to_vec(all), vec![1]);
+        assert!(queue.split_off(0).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "split index (is 3) should be <= len (is 2)")]
+    fn test_split_off_out_of_bounds() {
+        from_vec(vec![1, 2]).split_off(3);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut queue = from_vec(vec![0, 1, 2, 3, 4]);
+        for i in 0..5 {
+            assert_eq!(queue.get(i), Some(&(i as i32)));
+            assert_eq!(queue[i], i as i32);
+        }
+        assert_eq!(queue.get(5), None);
+        assert_eq!(queue.get_mut(5), None);
+        if let Some(val) = queue.get_mut(3) {
+            *val = 30;
+        }
+        queue[1] = 10;
+        assert_eq!(to_vec(queue), vec![0, 10, 2, 30, 4]);
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut queue = Queue::new();
+        queue.insert(0, 2);
+        queue.insert(0, 0);
+        queue.insert(1, 1);
+        queue.insert(3, 4);
+        queue.insert(3, 3);
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.remove(5), None);
+        assert_eq!(queue.remove(3), Some(3));
+        assert_eq!(queue.remove(0), Some(0));
+        assert_eq!(queue.remove(2), Some(4));
+        assert_eq!(queue.peek_back(), Some(&2));
+        assert_eq!(to_vec(queue), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5]);
+        queue.swap(0, 4);
+        queue.swap(1, 3);
+        queue.swap(2, 2);
+        assert_eq!(to_vec(queue), vec![5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 2 but the index is 2")]
+    fn test_index_out_of_bounds() {
+        let queue = from_vec(vec![1, 2]);
+        let _ = queue[2];
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 3) should be <= len (is 2)")]
+    fn test_insert_out_of_bounds() {
+        from_vec(vec![1, 2]).insert(3, 3);
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut queue = from_vec(vec![5, 3, 8, 1, 9, 2, 7, 4, 6, 0, 3]);
+        assert!(!queue.is_sorted());
+        queue.sort();
+        assert!(queue.is_sorted());
+        assert_eq!(queue.peek_back(), Some(&9));
+        assert_eq!(
+            queue.iter().rev().copied().collect::<Vec<_>>(),
+            vec![9, 8, 7, 6, 5, 4, 3, 3, 2, 1, 0]
+        );
+        queue.sort_by(|a, b| b.cmp(a));
+        queue.push_back(-1);
+        assert_eq!(to_vec(queue), vec![9, 8, 7, 6, 5, 4, 3, 3, 2, 1, 0, -1]);
+
+        let mut queue: Queue<i32> = Queue::new();
+        queue.sort();
+        assert!(queue.is_sorted());
+        queue.push_back(1);
+        queue.sort();
+        assert_eq!(queue.peek_back(), Some(&1));
+    }
+
+    #[test]
+    fn test_sort_is_stable() {
+        let mut queue = Queue::new();
+        for (i, key) in [3, 1, 2, 1, 3, 2, 1].iter().enumerate() {
+            queue.push_back((*key, i));
+        }
+        let first: *const _ = queue.peek_front().unwrap();
+        queue.sort_by_key(|&(key, _)| key);
+        let sorted: Vec<_> = queue.iter().copied().collect();
+        assert_eq!(
+            sorted,
+            vec![(1, 1), (1, 3), (1, 6), (2, 2), (2, 5), (3, 0), (3, 4)]
+        );
+        // Nodes are relinked rather than their elements being moved
+        assert!(ptr::eq(first, &queue[5]));
+    }
+
+    #[test]
+    fn test_sort_panic_safety() {
+        let testdrop = TestDrop::new();
+        let mut queue = Queue::new();
+        for _ in 0..9 {
+            queue.push_front(testdrop.new_item());
+        }
+
+        let mut comparisons = 0;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            queue.sort_by(|(a, _), (b, _)| {
+                comparisons += 1;
+                if comparisons == 12 {
+                    panic!("comparison failed");
+                }
+                a.cmp(b)
+            })
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(queue.len(), 9);
+        assert_eq!(queue.iter().count(), 9);
+        assert_eq!(queue.iter().rev().count(), 9);
+        let mut ids: Vec<_> = queue.iter().map(|(id, _)| *id).collect();
+        ids.sort();
+        assert_eq!(ids, (0..9).collect::<Vec<_>>());
+        assert_eq!(testdrop.num_dropped_items(), 0);
+        queue.sort_by_key(|(id, _)| *id);
+        assert_eq!(queue.pop_back().map(|(id, _)| id), Some(8));
+        drop(queue);
+        assert_eq!(testdrop.num_dropped_items(), 9);
+    }
+
+    #[test]
+    fn test_sort_matches_vec() {
+        let mut seed: u32 = 17;
+        for len in 0..40 {
+            let mut elems = Vec::new();
+            for _ in 0..len {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                elems.push((seed >> 16) as i32 % 10);
+            }
+            let mut queue = from_vec(elems.clone());
+            queue.sort();
+            elems.sort();
+            assert_eq!(queue.len(), len);
+            assert_eq!(queue.iter().rev().count(), len);
+            assert_eq!(to_vec(queue), elems);
+        }
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5, 6]);
+        queue.retain(|&val| val % 2 == 0);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.peek_front(), Some(&2));
+        assert_eq!(queue.peek_back(), Some(&6));
+        queue.retain_mut(|val| {
+            *val *= 10;
+            *val != 40
+        });
+        assert_eq!(
+            queue.iter().rev().copied().collect::<Vec<_>>(),
+            vec![60, 20]
+        );
+        queue.retain(|_| false);
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek_back(), None);
+        queue.push_front(1);
+        assert_eq!(to_vec(queue), vec![1]);
+    }
+
+    #[test]
+    fn test_extract_if() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5, 6, 7]);
+        {
+            let mut extracted = queue.extract_if(|val| *val % 3 != 0);
+            assert_eq!(extracted.next(), Some(1));
+            assert_eq!(extracted.next(), Some(2));
+            assert_eq!(extracted.next(), Some(4));
+        }
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.peek_front(), Some(&3));
+
+        let odd: Vec<_> = queue.extract_if(|val| *val % 2 == 1).collect();
+        assert_eq!(odd, vec![3, 5, 7]);
+        assert_eq!(queue.peek_back(), Some(&6));
+        assert_eq!(queue.iter().rev().copied().collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn test_extract_if_panic_safety() {
+        let testdrop = TestDrop::new();
+        let mut queue = Queue::new();
+        for _ in 0..6 {
+            queue.push_back(testdrop.new_item());
+        }
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            queue.retain(|(id, _)| match id {
+                4 => panic!("predicate failed"),
+                _ => id % 2 == 0,
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(testdrop.num_dropped_items(), 2);
+        assert_eq!(queue.len(), 4);
+        let ids: Vec<_> = queue.iter().rev().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![5, 4, 2, 0]);
+        drop(queue);
+        assert_eq!(testdrop.num_dropped_items(), 6);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut queue = from_vec(vec![1, 2, 3, 4]);
+        queue.reverse();
+        assert_eq!(queue.peek_front(), Some(&4));
+        assert_eq!(queue.peek_back(), Some(&1));
+        assert_eq!(
+            queue.iter().rev().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        queue.push_back(0);
+        queue.push_front(5);
+        assert_eq!(to_vec(queue), vec![5, 4, 3, 2, 1, 0]);
+
+        let mut queue: Queue<i32> = Queue::new();
+        queue.reverse();
+        assert!(queue.is_empty());
+        queue.push_back(1);
+        queue.reverse();
+        assert_eq!(to_vec(queue), vec![1]);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut queue = from_vec(vec![1, 2, 3, 4, 5]);
+        let first: *const _ = queue.peek_front().unwrap();
+        queue.rotate_left(2);
+        assert_eq!(
+            queue.iter().copied().collect::<Vec<_>>(),
+            vec![3, 4, 5, 1, 2]
+        );
+        assert!(ptr::eq(first, &queue[3]));
+        queue.rotate_right(1);
+        assert_eq!(
+            queue.iter().copied().collect::<Vec<_>>(),
+            vec![2, 3, 4, 5, 1]
+        );
+        queue.rotate_left(0);
+        queue.rotate_right(5);
+        assert_eq!(queue.peek_back(), Some(&1));
+        assert_eq!(
+            queue.iter().rev().copied().collect::<Vec<_>>(),
+            vec![1, 5, 4, 3, 2]
+        );
+        queue.rotate_left(4);
+        assert_eq!(queue.len(), 5);
+        assert_eq!(to_vec(queue), vec![1, 2, 3, 4, 5]);
+
+        let mut queue: Queue<i32> = Queue::new();
+        queue.rotate_left(0);
+        queue.rotate_right(0);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "rotation amount (is 3) should be <= len (is 2)")]
+    fn test_rotate_out_of_bounds() {
+        from_vec(vec![1, 2]).rotate_right(3);
+    }
+
+    #[test]
+    fn test_collect_extend() {
+        let mut queue: Queue<i32> = vec![1, 2, 3].into_iter().collect();
+        queue.extend(vec![4, 5]);
+        queue.extend(&[6]);
+        for elem in &mut queue {
+            *elem *= 10;
+        }
+        let mut elems = Vec::new();
+        for &elem in &queue {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![10, 20, 30, 40, 50, 60]);
+        let mut elems = Vec::new();
+        for elem in queue {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![10, 20, 30, 40, 50, 60]);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let queue: Queue<i32> = vec![1, 2, 3].into_iter().collect();
+        let copy = queue.clone();
+        assert_eq!(queue, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", Queue::<i32>::default()), "[]");
+        assert_eq!(hash_of(&queue), hash_of(&copy));
+
+        let shorter: Queue<i32> = vec![1, 2].into_iter().collect();
+        let bigger: Queue<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(queue, shorter);
+        assert!(shorter < queue);
+        assert!(queue < bigger);
+        assert_eq!(queue.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Queue<i32>>();
+        is_sync::<Queue<i32>>();
+        is_send::<IntoIter<i32>>();
+        is_sync::<IntoIter<i32>>();
+        is_send::<Iter<'static, i32>>();
+        is_sync::<Iter<'static, i32>>();
+        is_send::<IterMut<'static, i32>>();
+        is_sync::<IterMut<'static, i32>>();
+        is_send::<Cursor<'static, i32>>();
+        is_sync::<Cursor<'static, i32>>();
+        is_send::<CursorMut<'static, i32>>();
+        is_sync::<CursorMut<'static, i32>>();
+
+        let mut queue: Queue<String> = Queue::new();
+        queue.push_back(String::from("job"));
+        let handle = std::thread::spawn(move || queue.pop_back());
+        assert_eq!(handle.join().unwrap(), Some(String::from("job")));
+    }
+
+    #[test]
+    fn test_covariance() {
+        fn queue<'a>(queue: Queue<&'static str>) -> Queue<&'a str> {
+            queue
+        }
+        fn into_iter<'a>(iter: IntoIter<&'static str>) -> IntoIter<&'a str> {
+            iter
+        }
+        fn iter<'i, 'a>(iter: Iter<'i, &'static str>) -> Iter<'i, &'a str> {
+            iter
+        }
+        fn cursor<'c, 'a>(cursor: Cursor<'c, &'static str>) -> Cursor<'c, &'a str> {
+            cursor
+        }
+
+        let mut static_queue = Queue::new();
+        static_queue.push_back("static");
+        let local = String::from("local");
+        let mut queue = queue(static_queue);
+        queue.push_back(&local);
+        assert_eq!(queue.into_iter().next_back(), Some("local"));
+
+        let statics: Queue<&'static str> = vec!["static"].into_iter().collect();
+        assert_eq!(iter(statics.iter()).len(), 1);
+        assert_eq!(cursor(statics.cursor_back()).current(), Some(&"static"));
+        assert_eq!(into_iter(statics.into_iter()).next_back(), Some("static"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_size_alias() {
+        let mut queue = Queue::new();
+        queue.push_back(1);
+        queue.push_back(2);
+        assert_eq!(queue.size(), 2);
+    }
+
+    #[test]
+    fn test_node_alloc() {
+        let alloc = Counting::default();
+        let mut queue = Queue::new_in(alloc.clone());
+        queue.extend(1..=6);
+        assert_eq!(alloc.live.get(), 6);
+
+        let mut back = queue.split_off(4);
+        let mut cursor = queue.cursor_front_mut();
+        cursor.move_next();
+        let mut front = cursor.split_before();
+        let all = queue.split_off(0);
+        assert_eq!(alloc.live.get(), 6);
+
+        front.append(&mut back);
+        front.extract_if(|elem| *elem % 2 == 0).for_each(drop);
+        assert_eq!(alloc.live.get(), 5);
+        assert_eq!(to_vec(all), vec![2, 3, 4]);
+        assert_eq!(to_vec(front), vec![1, 5]);
+        drop(queue);
+        assert_eq!(alloc.live.get(), 0);
+        assert_eq!(alloc.total.get(), 6);
+    }
+
+    #[test]
+    fn test_append_foreign_alloc() {
+        let (alloc, other_alloc) = (Counting::default(), Counting::default());
+        let mut queue = Queue::new_in(alloc.clone());
+        let mut other = Queue::new_in(other_alloc.clone());
+        queue.extend(vec![3, 4]);
+        other.extend(vec![5, 6]);
+        queue.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(other_alloc.live.get(), 0);
+        assert_eq!(alloc.live.get(), 4);
+
+        other.extend(vec![1, 2]);
+        let mut cursor = queue.cursor_front_mut();
+        cursor.splice_before(other);
+        assert_eq!(cursor.index(), Some(2));
+        assert_eq!(cursor.current(), Some(&mut 3));
+        assert_eq!(other_alloc.live.get(), 0);
+        assert_eq!(alloc.live.get(), 6);
+
+        let mut back = Queue::new_in(other_alloc.clone());
+        back.push_back(7);
+        queue.append(&mut back);
+        assert_eq!(queue.peek_back(), Some(&7));
+        assert_eq!(
+            queue.iter().collect::<Vec<_>>(),
+            vec![&1, &2, &3, &4, &5, &6, &7]
+        );
+        drop(queue);
+        assert_eq!(alloc.live.get(), 0);
+        assert_eq!(other_alloc.live.get(), 0);
+    }
 }
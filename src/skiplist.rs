@@ -0,0 +1,1081 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::ptr::NonNull;
+
+const MAX_HEIGHT: usize = 32;
+
+// An ordered map on top of singly-linked nodes. Every node is linked into
+// the bottom level and into each level below its randomly chosen height, so
+// the upper levels skip over exponentially more nodes. Nodes are owned by
+// the bottom level; the other levels only point at them.
+//
+// In indexed mode every link also stores its width: how many bottom-level
+// steps it skips. A link to the end counts up to an imaginary node after
+// the last one, so inserting or removing a node changes the width of every
+// link passing over it by one.
+pub struct SkipList<K, V> {
+    head: Vec<Link<K, V>>,
+    widths: Vec<usize>,
+    len: usize,
+    indexed: bool,
+    rng: Rng,
+    marker: PhantomData<Box<Node<K, V>>>,
+}
+
+type Link<K, V> = Option<NonNull<Node<K, V>>>;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    next: Box<[Link<K, V>]>,
+    widths: Box<[usize]>,
+}
+
+// The levels searched for a key: for every level, the last position whose
+// key is less than it, with `None` standing for the head, and that
+// position's rank when the list is indexed.
+struct Path<K, V> {
+    prev: [Link<K, V>; MAX_HEIGHT],
+    ranks: [usize; MAX_HEIGHT],
+}
+
+// SplitMix64, seeded from `RandomState` unless the caller picks a seed.
+#[derive(Clone, Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Each level is kept with probability 1/2.
+    fn height(&mut self) -> usize {
+        (self.next_u64() | 1 << (MAX_HEIGHT - 1)).trailing_zeros() as usize + 1
+    }
+}
+
+impl<K: Ord, V> SkipList<K, V> {
+    pub fn new() -> Self {
+        SkipList::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        SkipList {
+            head: Vec::new(),
+            widths: Vec::new(),
+            len: 0,
+            indexed: false,
+            rng: Rng(seed),
+            marker: PhantomData,
+        }
+    }
+
+    // Switches on indexed mode, which makes `nth` O(log n) at the cost of
+    // one width per link. Widths of existing nodes are computed in one pass.
+    pub fn indexed(mut self) -> Self {
+        if !self.indexed {
+            self.indexed = true;
+            self.rebuild_widths();
+        }
+        self
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let path = self.search(&key);
+        if let Some(node) = self.next(path.prev[0], 0) {
+            let node = unsafe { &mut *node.as_ptr() };
+            if node.key == key {
+                return Some(std::mem::replace(&mut node.value, value));
+            }
+        }
+
+        let height = self.rng.height();
+        while self.head.len() < height {
+            self.head.push(None);
+            if self.indexed {
+                self.widths.push(self.len + 1);
+            }
+        }
+
+        let rank = path.ranks[0] + 1;
+        let node = Box::new(Node {
+            key,
+            value,
+            next: vec![None; height].into_boxed_slice(),
+            widths: vec![0; if self.indexed { height } else { 0 }].into_boxed_slice(),
+        });
+        let node = unsafe { NonNull::new_unchecked(Box::into_raw(node)) };
+
+        for level in 0..self.head.len() {
+            let (next, widths) = unsafe { self.links(path.prev[level]) };
+            if level < height {
+                unsafe {
+                    (*node.as_ptr()).next[level] = next[level];
+                    next[level] = Some(node);
+                    if let Some(width) = widths.get_mut(level) {
+                        let before = rank - path.ranks[level];
+                        (*node.as_ptr()).widths[level] = *width + 1 - before;
+                        *width = before;
+                    }
+                }
+            } else if let Some(width) = widths.get_mut(level) {
+                *width += 1;
+            }
+        }
+
+        self.len += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let path = self.search(key);
+        let node = self
+            .next(path.prev[0], 0)
+            .filter(|node| unsafe { (*node.as_ptr()).key.borrow() == key })?;
+        Some(unsafe { self.unlink(&path.prev, node) }.1)
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let node = self.next(None, 0)?;
+        Some(unsafe { self.unlink(&[None; MAX_HEIGHT], node) })
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .map(|node| unsafe { &mut (*node.as_ptr()).value })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.head
+            .first()
+            .copied()
+            .flatten()
+            .map(|node| unsafe { Self::entry(node) })
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let mut at = None;
+        for level in (0..self.head.len()).rev() {
+            while let Some(next) = self.next(at, level) {
+                at = Some(next);
+            }
+        }
+        at.map(|node| unsafe { Self::entry(node) })
+    }
+
+    // Returns the entry at `index` in key order, in O(log n) when the list is
+    // indexed and by walking the bottom level otherwise.
+    pub fn nth(&self, index: usize) -> Option<(&K, &V)> {
+        if index >= self.len {
+            return None;
+        }
+        if !self.indexed {
+            return self.iter().nth(index);
+        }
+
+        let target = index + 1;
+        let (mut at, mut rank) = (None, 0);
+        for level in (0..self.head.len()).rev() {
+            while let Some(next) = self.next(at, level) {
+                let width = self.width(at, level);
+                if rank + width > target {
+                    break;
+                }
+                rank += width;
+                at = Some(next);
+            }
+            if rank == target {
+                break;
+            }
+        }
+        at.map(|node| unsafe { Self::entry(node) })
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) if start == end => {
+                panic!("range start and end are equal and excluded in SkipList")
+            }
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) if start > end => panic!("range start is greater than range end in SkipList"),
+            _ => {}
+        }
+
+        let next = match range.start_bound() {
+            Bound::Included(start) => self.lower_bound(start, false),
+            Bound::Excluded(start) => self.lower_bound(start, true),
+            Bound::Unbounded => self.next(None, 0),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.lower_bound(end, true),
+            Bound::Excluded(end) => self.lower_bound(end, false),
+            Bound::Unbounded => None,
+        };
+        Range {
+            next,
+            end,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            next: self.next(None, 0),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    fn find<Q>(&self, key: &Q) -> Link<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let path = self.search(key);
+        self.next(path.prev[0], 0)
+            .filter(|node| unsafe { (*node.as_ptr()).key.borrow() == key })
+    }
+
+    // The first node whose key is not less than `key`, or greater than it if
+    // `strict`.
+    fn lower_bound<Q>(&self, key: &Q, strict: bool) -> Link<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let path = self.search(key);
+        let next = self.next(path.prev[0], 0);
+        match next {
+            Some(node) if strict && unsafe { (*node.as_ptr()).key.borrow() == key } => {
+                self.next(next, 0)
+            }
+            _ => next,
+        }
+    }
+
+    fn search<Q>(&self, key: &Q) -> Path<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut path = Path {
+            prev: [None; MAX_HEIGHT],
+            ranks: [0; MAX_HEIGHT],
+        };
+        let (mut at, mut rank) = (None, 0);
+        for level in (0..self.head.len()).rev() {
+            while let Some(next) = self.next(at, level) {
+                if unsafe { (*next.as_ptr()).key.borrow() } >= key {
+                    break;
+                }
+                if self.indexed {
+                    rank += self.width(at, level);
+                }
+                at = Some(next);
+            }
+            path.prev[level] = at;
+            path.ranks[level] = rank;
+        }
+        path
+    }
+
+    // Unlinks `node`, which must be the successor of `prev[level]` on every
+    // level it is linked into, and frees it.
+    unsafe fn unlink(
+        &mut self,
+        prev: &[Link<K, V>; MAX_HEIGHT],
+        node: NonNull<Node<K, V>>,
+    ) -> (K, V) {
+        let node = Box::from_raw(node.as_ptr());
+        for level in 0..self.head.len() {
+            let (next, widths) = self.links(prev[level]);
+            if level < node.next.len() {
+                next[level] = node.next[level];
+                if let Some(width) = widths.get_mut(level) {
+                    *width += node.widths[level] - 1;
+                }
+            } else if let Some(width) = widths.get_mut(level) {
+                *width -= 1;
+            }
+        }
+
+        while let Some(None) = self.head.last() {
+            self.head.pop();
+            self.widths.pop();
+        }
+        self.len -= 1;
+        let Node { key, value, .. } = *node;
+        (key, value)
+    }
+
+    fn rebuild_widths(&mut self) {
+        self.widths = vec![0; self.head.len()];
+        let mut last: Vec<(Link<K, V>, usize)> = vec![(None, 0); self.head.len()];
+        let mut curr = self.next(None, 0);
+        let mut rank = 0;
+        while let Some(node) = curr {
+            rank += 1;
+            let height = unsafe { node.as_ref().next.len() };
+            unsafe { (*node.as_ptr()).widths = vec![0; height].into_boxed_slice() };
+            for (level, (at, at_rank)) in last.iter_mut().enumerate().take(height) {
+                unsafe { self.links(*at).1[level] = rank - *at_rank };
+                *at = Some(node);
+                *at_rank = rank;
+            }
+            curr = self.next(curr, 0);
+        }
+        for (level, (at, at_rank)) in last.into_iter().enumerate() {
+            unsafe { self.links(at).1[level] = self.len + 1 - at_rank };
+        }
+    }
+}
+
+impl<K, V> SkipList<K, V> {
+    fn next(&self, at: Link<K, V>, level: usize) -> Link<K, V> {
+        match at {
+            Some(node) => unsafe { node.as_ref().next[level] },
+            None => self.head.get(level).copied().flatten(),
+        }
+    }
+
+    fn width(&self, at: Link<K, V>, level: usize) -> usize {
+        match at {
+            Some(node) => unsafe { node.as_ref().widths[level] },
+            None => self.widths[level],
+        }
+    }
+
+    // The links and widths of `at`, or of the head when `at` is `None`. `at`
+    // must be a node of this list.
+    unsafe fn links(&mut self, at: Link<K, V>) -> (&mut [Link<K, V>], &mut [usize]) {
+        match at {
+            Some(node) => {
+                let node = &mut *node.as_ptr();
+                (&mut node.next, &mut node.widths)
+            }
+            None => (&mut self.head, &mut self.widths),
+        }
+    }
+
+    unsafe fn entry<'a>(node: NonNull<Node<K, V>>) -> (&'a K, &'a V) {
+        let node = &*node.as_ptr();
+        (&node.key, &node.value)
+    }
+}
+
+unsafe impl<K: Send, V: Send> Send for SkipList<K, V> {}
+unsafe impl<K: Sync, V: Sync> Sync for SkipList<K, V> {}
+
+pub struct Iter<'a, K, V> {
+    next: Link<K, V>,
+    len: usize,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| unsafe {
+            self.next = node.as_ref().next[0];
+            self.len -= 1;
+            SkipList::entry(node)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for Iter<'a, K, V> {}
+
+unsafe impl<'a, K: Sync, V: Sync> Send for Iter<'a, K, V> {}
+unsafe impl<'a, K: Sync, V: Sync> Sync for Iter<'a, K, V> {}
+
+// Walks the bottom level until it reaches `end`, the first node past the
+// range, which `range` looked up in advance.
+pub struct Range<'a, K, V> {
+    next: Link<K, V>,
+    end: Link<K, V>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None;
+        }
+        self.next.map(|node| unsafe {
+            self.next = node.as_ref().next[0];
+            SkipList::entry(node)
+        })
+    }
+}
+
+impl<'a, K, V> FusedIterator for Range<'a, K, V> {}
+
+unsafe impl<'a, K: Sync, V: Sync> Send for Range<'a, K, V> {}
+unsafe impl<'a, K: Sync, V: Sync> Sync for Range<'a, K, V> {}
+
+pub struct IntoIter<K: Ord, V>(SkipList<K, V>);
+
+impl<K: Ord, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K: Ord, V> FusedIterator for IntoIter<K, V> {}
+
+impl<K: Ord, V> IntoIterator for SkipList<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a SkipList<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for SkipList<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K: Ord + Copy, V: Copy> Extend<(&'a K, &'a V)> for SkipList<K, V> {
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&key, &value)| (key, value)))
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SkipList<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut list = SkipList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+// The clone continues from a copy of the original's random state, so both
+// build the same towers from here on.
+impl<K: Ord + Clone, V: Clone> Clone for SkipList<K, V> {
+    fn clone(&self) -> Self {
+        let mut list = SkipList::with_seed(0);
+        list.indexed = self.indexed;
+        for (key, value) in self {
+            list.insert(key.clone(), value.clone());
+        }
+        list.rng = self.rng.clone();
+        list
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for SkipList<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        let mut curr = self.next(None, 0);
+        while let Some(node) = curr {
+            let (key, value) = unsafe { Self::entry(node) };
+            map.entry(key, value);
+            curr = unsafe { node.as_ref().next[0] };
+        }
+        map.finish()
+    }
+}
+
+impl<K: Ord, V: PartialEq> PartialEq for SkipList<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
+    }
+}
+
+impl<K: Ord, V: Eq> Eq for SkipList<K, V> {}
+
+impl<K: Ord, V: PartialOrd> PartialOrd for SkipList<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<K: Ord, V: Ord> Ord for SkipList<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<K: Ord + Hash, V: Hash> Hash for SkipList<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<K: Ord, V> Default for SkipList<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for SkipList<K, V> {
+    fn drop(&mut self) {
+        let mut curr = self.next(None, 0);
+        while let Some(node) = curr {
+            let node = unsafe { Box::from_raw(node.as_ptr()) };
+            curr = node.next[0];
+        }
+    }
+}
+
+// An ordered set on top of `SkipList`, with the same seeding and indexed
+// mode.
+pub struct SkipSet<K> {
+    map: SkipList<K, ()>,
+}
+
+impl<K: Ord> SkipSet<K> {
+    pub fn new() -> Self {
+        SkipSet {
+            map: SkipList::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        SkipSet {
+            map: SkipList::with_seed(seed),
+        }
+    }
+
+    pub fn indexed(self) -> Self {
+        SkipSet {
+            map: self.map.indexed(),
+        }
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.map.is_indexed()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // Returns whether the key was newly inserted.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.map.insert(key, ()).is_none()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(key).is_some()
+    }
+
+    pub fn pop_first(&mut self) -> Option<K> {
+        self.map.pop_first().map(|(key, ())| key)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        self.map.first().map(|(key, ())| key)
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        self.map.last().map(|(key, ())| key)
+    }
+
+    pub fn nth(&self, index: usize) -> Option<&K> {
+        self.map.nth(index).map(|(key, ())| key)
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> SetRange<'_, K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        SetRange(self.map.range(range))
+    }
+
+    pub fn iter(&self) -> SetIter<'_, K> {
+        SetIter(self.map.iter())
+    }
+}
+
+pub struct SetIter<'a, K>(Iter<'a, K, ()>);
+
+impl<'a, K> Iterator for SetIter<'a, K> {
+    type Item = &'a K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, ())| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K> ExactSizeIterator for SetIter<'a, K> {}
+
+impl<'a, K> FusedIterator for SetIter<'a, K> {}
+
+pub struct SetRange<'a, K>(Range<'a, K, ()>);
+
+impl<'a, K> Iterator for SetRange<'a, K> {
+    type Item = &'a K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, ())| key)
+    }
+}
+
+impl<'a, K> FusedIterator for SetRange<'a, K> {}
+
+pub struct SetIntoIter<K: Ord>(IntoIter<K, ()>);
+
+impl<K: Ord> Iterator for SetIntoIter<K> {
+    type Item = K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, ())| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K: Ord> ExactSizeIterator for SetIntoIter<K> {}
+
+impl<K: Ord> FusedIterator for SetIntoIter<K> {}
+
+impl<K: Ord> IntoIterator for SkipSet<K> {
+    type Item = K;
+    type IntoIter = SetIntoIter<K>;
+    fn into_iter(self) -> Self::IntoIter {
+        SetIntoIter(self.map.into_iter())
+    }
+}
+
+impl<'a, K: Ord> IntoIterator for &'a SkipSet<K> {
+    type Item = &'a K;
+    type IntoIter = SetIter<'a, K>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Ord> Extend<K> for SkipSet<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|key| (key, ())))
+    }
+}
+
+impl<'a, K: 'a + Ord + Copy> Extend<&'a K> for SkipSet<K> {
+    fn extend<I: IntoIterator<Item = &'a K>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<K: Ord> FromIterator<K> for SkipSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut set = SkipSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<K: Ord + Clone> Clone for SkipSet<K> {
+    fn clone(&self) -> Self {
+        SkipSet {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug> fmt::Debug for SkipSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        let mut curr = self.map.next(None, 0);
+        while let Some(node) = curr {
+            unsafe {
+                set.entry(&(*node.as_ptr()).key);
+                curr = node.as_ref().next[0];
+            }
+        }
+        set.finish()
+    }
+}
+
+impl<K: Ord> PartialEq for SkipSet<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<K: Ord> Eq for SkipSet<K> {}
+
+impl<K: Ord> PartialOrd for SkipSet<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> Ord for SkipSet<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<K: Ord + Hash> Hash for SkipSet<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.map.hash(state)
+    }
+}
+
+impl<K: Ord> Default for SkipSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::BTreeMap;
+    use testdrop::TestDrop;
+
+    // Checks the ordering of every level, that each level skips over a
+    // subset of the one below, and the widths of an indexed list.
+    fn check<K: Ord, V>(list: &SkipList<K, V>) {
+        let mut ranks = Vec::new();
+        let mut curr = list.next(None, 0);
+        while let Some(node) = curr {
+            ranks.push(node);
+            curr = list.next(curr, 0);
+        }
+        assert_eq!(ranks.len(), list.len());
+        let rank_of = |link: Link<K, V>| match link {
+            Some(node) => ranks.iter().position(|&n| n == node).unwrap() + 1,
+            None => list.len() + 1,
+        };
+
+        assert_ne!(list.head.last(), Some(&None));
+        for level in 0..list.head.len() {
+            let mut at = None;
+            let mut at_rank = 0;
+            loop {
+                let next = list.next(at, level);
+                let next_rank = rank_of(next);
+                assert!(next_rank > at_rank);
+                if list.indexed {
+                    assert_eq!(list.width(at, level), next_rank - at_rank);
+                }
+                match next {
+                    Some(_) => {
+                        at = next;
+                        at_rank = next_rank;
+                    }
+                    None => break,
+                }
+            }
+        }
+        assert!(list.iter().zip(list.iter().skip(1)).all(|(a, b)| a.0 < b.0));
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut list = SkipList::with_seed(1);
+        assert_eq!(list.insert(3, "c"), None);
+        assert_eq!(list.insert(1, "a"), None);
+        assert_eq!(list.insert(2, "b"), None);
+        assert_eq!(list.insert(2, "B"), Some("b"));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.get(&2), Some(&"B"));
+        assert_eq!(list.get(&4), None);
+        *list.get_mut(&3).unwrap() = "C";
+        assert!(list.contains_key(&3));
+        assert_eq!(list.remove(&1), Some("a"));
+        assert_eq!(list.remove(&1), None);
+        assert_eq!(
+            list.iter().collect::<Vec<_>>(),
+            vec![(&2, &"B"), (&3, &"C")]
+        );
+        check(&list);
+
+        let mut names = SkipList::with_seed(2);
+        names.insert(String::from("b"), 2);
+        names.insert(String::from("a"), 1);
+        assert_eq!(names.get("a"), Some(&1));
+        assert_eq!(names.remove("b"), Some(2));
+    }
+
+    #[test]
+    fn test_matches_btreemap() {
+        let mut rng = Rng(7);
+        let mut list = SkipList::with_seed(42).indexed();
+        let mut map = BTreeMap::new();
+        for _ in 0..2000 {
+            let key = rng.next_u64() % 300;
+            match rng.next_u64() % 3 {
+                0 => assert_eq!(list.remove(&key), map.remove(&key)),
+                _ => assert_eq!(list.insert(key, key * 2), map.insert(key, key * 2)),
+            }
+        }
+        check(&list);
+        assert_eq!(list.len(), map.len());
+        assert!(list.iter().eq(map.iter()));
+        for (i, entry) in map.iter().enumerate() {
+            assert_eq!(list.nth(i), Some(entry));
+        }
+        assert_eq!(list.nth(map.len()), None);
+        assert_eq!(list.first(), map.iter().next());
+        assert_eq!(list.last(), map.iter().next_back());
+    }
+
+    #[test]
+    fn test_seeded_towers() {
+        let heights = |seed| {
+            let list: SkipList<i32, ()> = {
+                let mut list = SkipList::with_seed(seed);
+                list.extend((0..100).map(|i| (i, ())));
+                list
+            };
+            let mut heights = Vec::new();
+            let mut curr = list.next(None, 0);
+            while let Some(node) = curr {
+                heights.push(unsafe { node.as_ref().next.len() });
+                curr = list.next(curr, 0);
+            }
+            heights
+        };
+        assert_eq!(heights(5), heights(5));
+        assert_ne!(heights(5), heights(6));
+        assert!(heights(5).iter().any(|&height| height > 1));
+    }
+
+    #[test]
+    fn test_indexed_later() {
+        let mut list = SkipList::with_seed(3);
+        list.extend((0..50).map(|i| (i * 2, i)));
+        assert!(!list.is_indexed());
+        assert_eq!(list.nth(10), Some((&20, &10)));
+
+        let mut list = list.indexed();
+        assert!(list.is_indexed());
+        check(&list);
+        list.remove(&20);
+        list.insert(21, 0);
+        assert_eq!(list.nth(10), Some((&21, &0)));
+        assert_eq!(list.nth(0), Some((&0, &0)));
+        assert_eq!(list.nth(49), Some((&98, &49)));
+        check(&list);
+    }
+
+    #[test]
+    fn test_first_last() {
+        let mut list = SkipList::with_seed(4);
+        assert_eq!(list.first(), None);
+        assert_eq!(list.last(), None);
+        list.extend(vec![(5, 'e'), (1, 'a'), (9, 'i')]);
+        assert_eq!(list.first(), Some((&1, &'a')));
+        assert_eq!(list.last(), Some((&9, &'i')));
+        assert_eq!(list.pop_first(), Some((1, 'a')));
+        assert_eq!(list.first(), Some((&5, &'e')));
+        list.remove(&9);
+        assert_eq!(list.last(), Some((&5, &'e')));
+        check(&list);
+    }
+
+    #[test]
+    fn test_range() {
+        let list: SkipList<i32, ()> = (0..20).map(|i| (i * 5, ())).collect();
+        let keys = |range: Range<'_, i32, ()>| range.map(|(&key, _)| key).collect::<Vec<_>>();
+        assert_eq!(keys(list.range(10..25)), vec![10, 15, 20]);
+        assert_eq!(keys(list.range(11..=25)), vec![15, 20, 25]);
+        assert_eq!(keys(list.range(..7)), vec![0, 5]);
+        assert_eq!(keys(list.range(90..)), vec![90, 95]);
+        assert_eq!(keys(list.range(96..)), Vec::<i32>::new());
+        assert_eq!(keys(list.range(12..13)), Vec::<i32>::new());
+        assert_eq!(keys(list.range(20..20)), Vec::<i32>::new());
+        assert_eq!(
+            keys(list.range((Bound::Excluded(10), Bound::Included(20)))),
+            vec![15, 20]
+        );
+        assert_eq!(list.range(..).count(), 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "range start is greater than range end")]
+    fn test_range_backwards() {
+        let list: SkipList<i32, ()> = (0..5).map(|i| (i, ())).collect();
+        list.range((Bound::Included(3), Bound::Excluded(1)));
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let mut list = SkipList::with_seed(8);
+        for i in 0..10 {
+            list.insert(i, testdrop.new_item());
+        }
+        list.remove(&3);
+        list.insert(4, testdrop.new_item());
+        assert_eq!(2, testdrop.num_dropped_items());
+        drop(list);
+        assert_eq!(11, testdrop.num_dropped_items());
+    }
+
+    #[test]
+    fn test_set() {
+        let mut set = SkipSet::with_seed(9).indexed();
+        assert!(set.insert(3));
+        assert!(set.insert(1));
+        assert!(!set.insert(3));
+        set.extend(vec![7, 5]);
+        assert_eq!(set.len(), 4);
+        assert!(set.contains(&5));
+        assert!(set.remove(&5));
+        assert!(!set.remove(&5));
+        assert_eq!(set.first(), Some(&1));
+        assert_eq!(set.last(), Some(&7));
+        assert_eq!(set.nth(1), Some(&3));
+        assert_eq!(set.range(2..).collect::<Vec<_>>(), vec![&3, &7]);
+        assert_eq!(format!("{:?}", set), "{1, 3, 7}");
+        assert_eq!(set.pop_first(), Some(1));
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![3, 7]);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let list: SkipList<i32, char> = vec![(2, 'b'), (1, 'a')].into_iter().collect();
+        let copy = list.clone().indexed();
+        assert_eq!(list, copy);
+        check(&copy);
+        assert_eq!(format!("{:?}", copy), "{1: 'a', 2: 'b'}");
+        assert_eq!(format!("{:?}", SkipList::<i32, i32>::default()), "{}");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let mut shorter = SkipList::new();
+        shorter.extend(vec![(&1, &'a')]);
+        let bigger: SkipList<i32, char> = vec![(1, 'b')].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+
+        let set: SkipSet<i32> = vec![3, 1, 2].into_iter().collect();
+        let copy = set.clone();
+        assert_eq!(set, copy);
+        assert_eq!(hash_of(&set), hash_of(&copy));
+        assert!(SkipSet::default() < set);
+        assert_eq!(
+            set.iter().chain(&copy).copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<SkipList<i32, String>>();
+        is_sync::<SkipList<i32, String>>();
+        is_send::<Iter<'static, i32, String>>();
+        is_sync::<Range<'static, i32, String>>();
+        is_send::<SkipSet<i32>>();
+    }
+}
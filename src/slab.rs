@@ -0,0 +1,678 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+
+// A doubly-linked list whose nodes live in a single `Vec` and point at each
+// other by index. Vacant slots are chained through `next` into a free list
+// and reused by later insertions; every reuse bumps the slot's generation so
+// that `Handle`s to the old element stop matching.
+pub struct List<T> {
+    slots: Vec<Slot<T>>,
+    head: Option<u32>,
+    tail: Option<u32>,
+    free: Option<u32>,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    elem: Option<T>,
+    generation: u32,
+    next: Option<u32>,
+    prev: Option<u32>,
+}
+
+// Identifies one element for as long as it stays in the list it came from.
+// Generations never wrap: a slot is retired once its generation reaches
+// `u32::MAX`, so a stale handle never matches a later element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            slots: Vec::new(),
+            head: None,
+            tail: None,
+            free: None,
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        List {
+            slots: Vec::with_capacity(capacity),
+            ..List::new()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn push_front(&mut self, elem: T) -> Handle {
+        let index = self.alloc_slot(elem);
+        self.link_after(None, index);
+        self.handle(index)
+    }
+
+    pub fn push_back(&mut self, elem: T) -> Handle {
+        let index = self.alloc_slot(elem);
+        self.link_after(self.tail, index);
+        self.handle(index)
+    }
+
+    pub fn insert_after(&mut self, handle: Handle, elem: T) -> Option<Handle> {
+        self.index_of(handle)?;
+        let index = self.alloc_slot(elem);
+        self.link_after(Some(handle.index), index);
+        Some(self.handle(index))
+    }
+
+    pub fn insert_before(&mut self, handle: Handle, elem: T) -> Option<Handle> {
+        let prev = self.slots[self.index_of(handle)?].prev;
+        let index = self.alloc_slot(elem);
+        self.link_after(prev, index);
+        Some(self.handle(index))
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.map(|index| self.free_slot(index))
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tail.map(|index| self.free_slot(index))
+    }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        self.head
+            .and_then(|index| self.slots[index as usize].elem.as_ref())
+    }
+
+    pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+        let index = self.head?;
+        self.slots[index as usize].elem.as_mut()
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        self.tail
+            .and_then(|index| self.slots[index as usize].elem.as_ref())
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+        let index = self.tail?;
+        self.slots[index as usize].elem.as_mut()
+    }
+
+    pub fn front_handle(&self) -> Option<Handle> {
+        self.head.map(|index| self.handle(index))
+    }
+
+    pub fn back_handle(&self) -> Option<Handle> {
+        self.tail.map(|index| self.handle(index))
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.index_of(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        let index = self.index_of(handle)?;
+        self.slots[index].elem.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        let index = self.index_of(handle)?;
+        self.slots[index].elem.as_mut()
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        self.index_of(handle)?;
+        Some(self.free_slot(handle.index))
+    }
+
+    // Returns `false` and leaves the list alone if `handle` is stale.
+    pub fn move_to_front(&mut self, handle: Handle) -> bool {
+        if self.index_of(handle).is_none() {
+            return false;
+        }
+        self.unlink(handle.index);
+        self.link_after(None, handle.index);
+        true
+    }
+
+    pub fn move_to_back(&mut self, handle: Handle) -> bool {
+        if self.index_of(handle).is_none() {
+            return false;
+        }
+        self.unlink(handle.index);
+        self.link_after(self.tail, handle.index);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            slots: &self.slots,
+            head: self.head,
+            tail: self.tail,
+            len: self.len,
+        }
+    }
+
+    // Handing out several `&mut T` from the slab without `unsafe` means
+    // splitting the borrow up front, so this takes one pass and one
+    // allocation over every slot, vacant ones included.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            slots: self
+                .slots
+                .iter_mut()
+                .map(
+                    |Slot {
+                         elem, next, prev, ..
+                     }| (elem.as_mut(), *next, *prev),
+                )
+                .collect(),
+            head: self.head,
+            tail: self.tail,
+            len: self.len,
+        }
+    }
+
+    fn handle(&self, index: u32) -> Handle {
+        Handle {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+
+    // Returns the slot index of `handle` if it still refers to an element.
+    fn index_of(&self, handle: Handle) -> Option<usize> {
+        let index = handle.index as usize;
+        match self.slots.get(index) {
+            Some(slot) if slot.generation == handle.generation && slot.elem.is_some() => {
+                Some(index)
+            }
+            _ => None,
+        }
+    }
+
+    // Stores `elem` in a vacant slot, reusing one from the free list if possible.
+    // The slot is not linked into the list yet.
+    fn alloc_slot(&mut self, elem: T) -> u32 {
+        match self.free {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                self.free = slot.next;
+                slot.elem = Some(elem);
+                index
+            }
+            None => {
+                let index = u32::try_from(self.slots.len()).expect("slab list capacity overflow");
+                self.slots.push(Slot {
+                    elem: Some(elem),
+                    generation: 0,
+                    next: None,
+                    prev: None,
+                });
+                index
+            }
+        }
+    }
+
+    // Unlinks the occupied slot at `index`, retires its generation and puts it
+    // on the free list unless the generation is exhausted.
+    fn free_slot(&mut self, index: u32) -> T {
+        self.unlink(index);
+        let slot = &mut self.slots[index as usize];
+        let elem = slot.elem.take().unwrap();
+        // A slot whose generation has run out is never reused, so no handle
+        // can alias a later element.
+        if slot.generation < u32::MAX {
+            slot.generation += 1;
+            slot.next = self.free;
+            self.free = Some(index);
+        }
+        elem
+    }
+
+    // Links the slot at `index` right after `prev`, or at the front when `prev`
+    // is `None`.
+    fn link_after(&mut self, prev: Option<u32>, index: u32) {
+        let next = match prev {
+            Some(prev) => self.slots[prev as usize].next,
+            None => self.head,
+        };
+
+        let slot = &mut self.slots[index as usize];
+        slot.prev = prev;
+        slot.next = next;
+
+        match prev {
+            Some(prev) => self.slots[prev as usize].next = Some(index),
+            None => self.head = Some(index),
+        }
+        match next {
+            Some(next) => self.slots[next as usize].prev = Some(index),
+            None => self.tail = Some(index),
+        }
+        self.len += 1;
+    }
+
+    fn unlink(&mut self, index: u32) {
+        let (prev, next) = {
+            let slot = &mut self.slots[index as usize];
+            (slot.prev.take(), slot.next.take())
+        };
+
+        match prev {
+            Some(prev) => self.slots[prev as usize].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.slots[next as usize].prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+pub struct Iter<'a, T> {
+    slots: &'a [Slot<T>],
+    head: Option<u32>,
+    tail: Option<u32>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.head.map(|index| {
+            let slot = &self.slots[index as usize];
+            self.len -= 1;
+            self.head = slot.next;
+            slot.elem.as_ref().unwrap()
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.tail.map(|index| {
+            let slot = &self.slots[index as usize];
+            self.len -= 1;
+            self.tail = slot.prev;
+            slot.elem.as_ref().unwrap()
+        })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+// Like `Iter`, but over the elements borrowed out of the slots, each taken
+// the first time either end reaches it.
+pub struct IterMut<'a, T> {
+    slots: Vec<(Option<&'a mut T>, Option<u32>, Option<u32>)>,
+    head: Option<u32>,
+    tail: Option<u32>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let (elem, next, _) = &mut self.slots[self.head? as usize];
+        self.len -= 1;
+        self.head = *next;
+        elem.take()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let (elem, _, prev) = &mut self.slots[self.tail? as usize];
+        self.len -= 1;
+        self.tail = *prev;
+        elem.take()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy> Extend<&'a T> for List<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+// Cloning copies the slab as is, so handles into the original also refer to
+// the same elements of the clone.
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List {
+            slots: self.slots.clone(),
+            head: self.head,
+            tail: self.tail,
+            free: self.free,
+            len: self.len,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use testdrop::TestDrop;
+
+    #[test]
+    fn test_push_pop() {
+        let mut list = List::new();
+        list.push_back(2);
+        list.push_back(3);
+        list.push_front(1);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.peek_front(), Some(&1));
+        assert_eq!(list.peek_back(), Some(&3));
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), Some(2));
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut list = List::new();
+        let a = list.push_back("a");
+        let b = list.push_back("b");
+        let c = list.push_back("c");
+        assert_eq!(list.get(b), Some(&"b"));
+        *list.get_mut(c).unwrap() = "C";
+        assert_eq!(list.remove(b), Some("b"));
+        assert_eq!(list.remove(b), None);
+        assert_eq!(list.get(b), None);
+        assert!(!list.contains(b));
+
+        // The new element reuses b's slot but must not be reachable through b
+        let d = list.push_front("d");
+        assert_eq!(list.get(b), None);
+        assert_eq!(list.get(d), Some(&"d"));
+        assert_eq!(list.slots.len(), 3);
+
+        let e = list.insert_after(a, "e").unwrap();
+        list.insert_before(a, "f").unwrap();
+        assert_eq!(list.insert_after(b, "g"), None);
+        assert_eq!(list.get(e), Some(&"e"));
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            vec!["d", "f", "a", "e", "C"]
+        );
+        assert_eq!(list.front_handle(), Some(d));
+        assert_eq!(list.back_handle(), Some(c));
+    }
+
+    #[test]
+    fn test_generation_exhausted() {
+        let mut list = List::new();
+        let a = list.push_back(1);
+        list.remove(a);
+        list.slots[a.index as usize].generation = u32::MAX - 1;
+
+        let b = list.push_back(2);
+        assert_eq!(b.index, a.index);
+        list.remove(b);
+        let c = list.push_back(3);
+        assert_eq!((c.index, c.generation), (a.index, u32::MAX));
+        assert_eq!(list.remove(c), Some(3));
+
+        // The slot is retired rather than reused with a wrapped generation.
+        let d = list.push_back(4);
+        assert_ne!(d.index, c.index);
+        assert_eq!(list.slots.len(), 2);
+        assert_eq!(list.get(b), None);
+        assert_eq!(list.get(c), None);
+        assert_eq!(list.get(d), Some(&4));
+    }
+
+    #[test]
+    fn test_move_to_front() {
+        let mut list = List::new();
+        let handles: Vec<_> = (0..4).map(|i| list.push_back(i)).collect();
+        assert!(list.move_to_front(handles[2]));
+        assert!(list.move_to_front(handles[2]));
+        assert!(list.move_to_back(handles[0]));
+        assert!(list.move_to_front(handles[3]));
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+        assert_eq!(list.len(), 4);
+
+        list.remove(handles[1]);
+        assert!(!list.move_to_front(handles[1]));
+        assert!(!list.move_to_back(handles[1]));
+        assert_eq!(
+            list.iter().rev().copied().collect::<Vec<_>>(),
+            vec![0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut list: List<i32> = (1..=5).collect();
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&5));
+        assert_eq!(iter.collect::<Vec<_>>(), vec![&2, &3, &4]);
+
+        let front = list.front_handle().unwrap();
+        list.move_to_back(front);
+        for elem in &mut list {
+            *elem *= 10;
+        }
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.next_back(), Some(&mut 10));
+        assert_eq!(iter.next(), Some(&mut 20));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(
+            list.into_iter().rev().collect::<Vec<_>>(),
+            vec![10, 50, 40, 30, 20]
+        );
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let mut list = List::new();
+        let handles: Vec<_> = (0..5)
+            .map(|_| list.push_back(testdrop.new_item()))
+            .collect();
+        list.remove(handles[1]);
+        list.pop_front();
+        assert_eq!(2, testdrop.num_dropped_items());
+        list.push_back(testdrop.new_item());
+        drop(list);
+        assert_eq!(6, testdrop.num_dropped_items());
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        let handle = list.push_front(0);
+        list.remove(handle);
+        let copy = list.clone();
+        assert_eq!(list, copy);
+        assert_eq!(copy.get(handle), None);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let mut shorter = List::with_capacity(2);
+        shorter.extend(&[1, 2]);
+        let bigger: List<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(copy.len(), 3);
+    }
+}
@@ -0,0 +1,578 @@
+use crate::fifth;
+use crate::third::{self, ArcKind};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+// A `fifth::Queue` shared between threads. The queue keeps up to
+// `RECYCLED_NODES` popped nodes around, so once warmed up most pushes reuse
+// them instead of allocating; the bound itself is only a limit and is never
+// allocated up front. Pushers wait on `not_full` and poppers on `not_empty`;
+// closing wakes both.
+//
+// None of the queue's operations can leave it half updated, so a panic while
+// the lock is held does not make it unusable and poisoning is ignored.
+pub struct BoundedQueue<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+struct State<T> {
+    queue: fifth::Queue<T>,
+    closed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushError<T>(pub T);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryPushError<T> {
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushTimeoutError<T> {
+    Timeout(T),
+    Closed(T),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryPopError {
+    Empty,
+    Closed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PopTimeoutError {
+    Timeout,
+    Closed,
+}
+
+const RECYCLED_NODES: usize = 64;
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        BoundedQueue {
+            state: Mutex::new(State {
+                queue: fifth::Queue::with_capacity(capacity.min(RECYCLED_NODES)),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().queue.is_empty()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    // Blocks while the queue is full. Fails, handing the element back, once
+    // the queue is closed.
+    pub fn push(&self, elem: T) -> Result<(), PushError<T>> {
+        let state = self
+            .not_full
+            .wait_while(self.lock(), |state| self.is_full(state))
+            .unwrap_or_else(PoisonError::into_inner);
+        self.push_locked(state, elem).map_err(PushError)
+    }
+
+    pub fn try_push(&self, elem: T) -> Result<(), TryPushError<T>> {
+        let state = self.lock();
+        if self.is_full(&state) {
+            return Err(TryPushError::Full(elem));
+        }
+        self.push_locked(state, elem).map_err(TryPushError::Closed)
+    }
+
+    pub fn push_timeout(&self, elem: T, timeout: Duration) -> Result<(), PushTimeoutError<T>> {
+        let (state, result) = self
+            .not_full
+            .wait_timeout_while(self.lock(), timeout, |state| self.is_full(state))
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            return Err(PushTimeoutError::Timeout(elem));
+        }
+        self.push_locked(state, elem)
+            .map_err(PushTimeoutError::Closed)
+    }
+
+    // Blocks while the queue is empty. Elements pushed before closing are
+    // still handed out; after that it returns `None`.
+    pub fn pop(&self) -> Option<T> {
+        let state = self
+            .not_empty
+            .wait_while(self.lock(), |state| Self::is_starved(state))
+            .unwrap_or_else(PoisonError::into_inner);
+        self.pop_locked(state)
+    }
+
+    pub fn try_pop(&self) -> Result<T, TryPopError> {
+        let state = self.lock();
+        if Self::is_starved(&state) {
+            return Err(TryPopError::Empty);
+        }
+        self.pop_locked(state).ok_or(TryPopError::Closed)
+    }
+
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        let (state, result) = self
+            .not_empty
+            .wait_timeout_while(self.lock(), timeout, |state| Self::is_starved(state))
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            return Err(PopTimeoutError::Timeout);
+        }
+        self.pop_locked(state).ok_or(PopTimeoutError::Closed)
+    }
+
+    // Makes every later push fail and wakes all waiting threads. Poppers
+    // drain what is left before seeing the queue as closed.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_full(&self, state: &State<T>) -> bool {
+        !state.closed && state.queue.len() >= self.capacity
+    }
+
+    fn is_starved(state: &State<T>) -> bool {
+        !state.closed && state.queue.is_empty()
+    }
+
+    fn push_locked(&self, mut state: MutexGuard<'_, State<T>>, elem: T) -> Result<(), T> {
+        if state.closed {
+            return Err(elem);
+        }
+        state.queue.push(elem);
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    fn pop_locked(&self, mut state: MutexGuard<'_, State<T>>) -> Option<T> {
+        let elem = state.queue.pop()?;
+        drop(state);
+        self.not_full.notify_one();
+        Some(elem)
+    }
+}
+
+impl<T> fmt::Debug for BoundedQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("BoundedQueue")
+            .field("len", &state.queue.len())
+            .field("capacity", &self.capacity)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+// Handles to a queue shared by any number of senders and receivers. The
+// queue is closed when either side has no handles left, so a receiver sees
+// the end of the stream and a sender learns that nobody is listening.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: BoundedQueue::new(capacity),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    queue: BoundedQueue<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    pub fn push(&self, elem: T) -> Result<(), PushError<T>> {
+        self.shared.queue.push(elem)
+    }
+
+    pub fn try_push(&self, elem: T) -> Result<(), TryPushError<T>> {
+        self.shared.queue.try_push(elem)
+    }
+
+    pub fn push_timeout(&self, elem: T, timeout: Duration) -> Result<(), PushTimeoutError<T>> {
+        self.shared.queue.push_timeout(elem, timeout)
+    }
+
+    pub fn close(&self) {
+        self.shared.queue.close()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn pop(&self) -> Option<T> {
+        self.shared.queue.pop()
+    }
+
+    pub fn try_pop(&self) -> Result<T, TryPopError> {
+        self.shared.queue.try_pop()
+    }
+
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        self.shared.queue.pop_timeout(timeout)
+    }
+
+    pub fn close(&self) {
+        self.shared.queue.close()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+
+    // Blocks for each element in turn until the queue is closed and empty.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Receiver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.shared.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Sender").field(&self.shared.queue).finish()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Receiver").field(&self.shared.queue).finish()
+    }
+}
+
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.pop()
+    }
+}
+
+pub struct IntoIter<T>(Receiver<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("pushing on a closed queue")
+    }
+}
+
+impl<T> fmt::Display for TryPushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryPushError::Full(_) => f.write_str("pushing on a full queue"),
+            TryPushError::Closed(_) => f.write_str("pushing on a closed queue"),
+        }
+    }
+}
+
+impl<T> fmt::Display for PushTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushTimeoutError::Timeout(_) => f.write_str("timed out pushing on a full queue"),
+            PushTimeoutError::Closed(_) => f.write_str("pushing on a closed queue"),
+        }
+    }
+}
+
+impl fmt::Display for TryPopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryPopError::Empty => f.write_str("popping from an empty queue"),
+            TryPopError::Closed => f.write_str("popping from a closed and empty queue"),
+        }
+    }
+}
+
+impl fmt::Display for PopTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PopTimeoutError::Timeout => f.write_str("timed out popping from an empty queue"),
+            PopTimeoutError::Closed => f.write_str("popping from a closed and empty queue"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for PushError<T> {}
+impl<T: fmt::Debug> Error for TryPushError<T> {}
+impl<T: fmt::Debug> Error for PushTimeoutError<T> {}
+impl Error for TryPopError {}
+impl Error for PopTimeoutError {}
+
+// `third::List` sharing its nodes through `Arc`, so snapshots can be sent
+// to other threads.
+pub type PersistentList<T> = third::List<T, ArcKind>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+    use testdrop::TestDrop;
+
+    const SHORT: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn test_try_push_pop() {
+        let queue = BoundedQueue::new(2);
+        assert_eq!(queue.try_pop(), Err(TryPopError::Empty));
+        assert_eq!(queue.try_push(1), Ok(()));
+        assert_eq!(queue.try_push(2), Ok(()));
+        assert_eq!(queue.try_push(3), Err(TryPushError::Full(3)));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.try_pop(), Ok(1));
+        assert_eq!(queue.try_push(3), Ok(()));
+
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.try_push(4), Err(TryPushError::Closed(4)));
+        assert_eq!(queue.push(4), Err(PushError(4)));
+        assert_eq!(queue.try_pop(), Ok(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.try_pop(), Err(TryPopError::Closed));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_huge_bound() {
+        let (tx, rx) = channel(usize::MAX);
+        tx.try_push(1).unwrap();
+        assert_eq!(rx.try_pop(), Ok(1));
+        assert_eq!(BoundedQueue::<i32>::new(1 << 40).capacity(), 1 << 40);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let queue = BoundedQueue::new(1);
+        let start = Instant::now();
+        assert_eq!(queue.pop_timeout(SHORT), Err(PopTimeoutError::Timeout));
+        assert!(start.elapsed() >= SHORT);
+
+        queue.push(1).unwrap();
+        assert_eq!(
+            queue.push_timeout(2, SHORT),
+            Err(PushTimeoutError::Timeout(2))
+        );
+        assert_eq!(queue.pop_timeout(SHORT), Ok(1));
+        assert_eq!(queue.push_timeout(2, SHORT), Ok(()));
+
+        queue.close();
+        assert_eq!(queue.pop_timeout(SHORT), Ok(2));
+        assert_eq!(queue.pop_timeout(SHORT), Err(PopTimeoutError::Closed));
+        assert_eq!(
+            queue.push_timeout(3, SHORT),
+            Err(PushTimeoutError::Closed(3))
+        );
+    }
+
+    #[test]
+    fn test_blocking_order() {
+        let queue = BoundedQueue::new(2);
+        let popped = thread::scope(|s| {
+            let consumer = s.spawn(|| queue_iter(&queue).collect::<Vec<_>>());
+            for i in 0..1000 {
+                queue.push(i).unwrap();
+                assert!(queue.len() <= 2);
+            }
+            queue.close();
+            consumer.join().unwrap()
+        });
+        assert_eq!(popped, (0..1000).collect::<Vec<_>>());
+    }
+
+    fn queue_iter<T>(queue: &BoundedQueue<T>) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || queue.pop())
+    }
+
+    #[test]
+    fn test_close_wakes_waiters() {
+        let empty = BoundedQueue::<i32>::new(1);
+        let full = BoundedQueue::new(1);
+        full.push(0).unwrap();
+        thread::scope(|s| {
+            let popper = s.spawn(|| empty.pop());
+            let pusher = s.spawn(|| full.push(1));
+            thread::sleep(SHORT);
+            empty.close();
+            full.close();
+            assert_eq!(popper.join().unwrap(), None);
+            assert_eq!(pusher.join().unwrap(), Err(PushError(1)));
+        });
+    }
+
+    #[test]
+    fn test_channel() {
+        let (tx, rx) = channel(4);
+        let sums: Vec<u64> = thread::scope(|s| {
+            for t in 0..4u64 {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for i in 0..250 {
+                        tx.push(t * 1000 + i).unwrap();
+                    }
+                });
+            }
+            drop(tx);
+            let consumers: Vec<_> = (0..3)
+                .map(|_| {
+                    let rx = rx.clone();
+                    s.spawn(move || rx.into_iter().sum::<u64>())
+                })
+                .collect();
+            consumers.into_iter().map(|c| c.join().unwrap()).collect()
+        });
+        let expected: u64 = (0..4u64)
+            .flat_map(|t| (0..250).map(move |i| t * 1000 + i))
+            .sum();
+        assert_eq!(sums.iter().sum::<u64>(), expected);
+        assert!(rx.is_closed());
+        assert_eq!(rx.pop(), None);
+    }
+
+    #[test]
+    fn test_last_receiver_closes() {
+        let (tx, rx) = channel(1);
+        let rx2 = rx.clone();
+        tx.push(1).unwrap();
+        drop(rx);
+        assert!(!tx.is_closed());
+        assert_eq!(rx2.iter().next(), Some(1));
+        drop(rx2);
+        assert!(tx.is_closed());
+        assert_eq!(tx.try_push(2), Err(TryPushError::Closed(2)));
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let queue = BoundedQueue::new(3);
+        for _ in 0..3 {
+            queue.push(testdrop.new_item()).unwrap();
+        }
+        let rejected = match queue.try_push(testdrop.new_item()) {
+            Err(TryPushError::Full(item)) => item,
+            _ => panic!("queue should be full"),
+        };
+        assert_eq!(0, testdrop.num_dropped_items());
+        drop(rejected);
+        drop(queue.pop());
+        assert_eq!(2, testdrop.num_dropped_items());
+        drop(queue);
+        assert_eq!(4, testdrop.num_dropped_items());
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<BoundedQueue<i32>>();
+        is_sync::<BoundedQueue<i32>>();
+        is_send::<Sender<i32>>();
+        is_send::<Receiver<i32>>();
+    }
+}
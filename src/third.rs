@@ -1,33 +1,87 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::Arc;
 
-pub struct List<T> {
-    head: Link<T>,
+pub struct List<T, P: PointerKind = RcKind> {
+    head: Link<T, P>,
 }
 
-type Link<T> = Option<Rc<Node<T>>>;
+type Link<T, P> = Option<<P as PointerKind>::Pointer<Node<T, P>>>;
 
-struct Node<T> {
+struct Node<T, P: PointerKind> {
     elem: T,
-    next: Link<T>,
-    size: u32,
+    next: Link<T, P>,
+    len: usize,
+}
+
+// The reference-counted pointer the nodes are shared through. `RcKind` is
+// the default; `ArcKind` makes a list `Send` and `Sync` when its elements
+// are, so snapshots can be handed to other threads.
+pub trait PointerKind {
+    type Pointer<U>: Deref<Target = U> + Clone;
+    fn new<U>(value: U) -> Self::Pointer<U>;
+    fn try_unwrap<U>(pointer: Self::Pointer<U>) -> Result<U, Self::Pointer<U>>;
+    fn into_inner<U>(pointer: Self::Pointer<U>) -> Option<U>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RcKind;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArcKind;
+
+impl PointerKind for RcKind {
+    type Pointer<U> = Rc<U>;
+    fn new<U>(value: U) -> Rc<U> {
+        Rc::new(value)
+    }
+    fn try_unwrap<U>(pointer: Rc<U>) -> Result<U, Rc<U>> {
+        Rc::try_unwrap(pointer)
+    }
+    fn into_inner<U>(pointer: Rc<U>) -> Option<U> {
+        Rc::into_inner(pointer)
+    }
+}
+
+impl PointerKind for ArcKind {
+    type Pointer<U> = Arc<U>;
+    fn new<U>(value: U) -> Arc<U> {
+        Arc::new(value)
+    }
+    fn try_unwrap<U>(pointer: Arc<U>) -> Result<U, Arc<U>> {
+        Arc::try_unwrap(pointer)
+    }
+    fn into_inner<U>(pointer: Arc<U>) -> Option<U> {
+        Arc::into_inner(pointer)
+    }
 }
 
 impl<T> List<T> {
-    pub fn new() -> List<T> {
+    pub fn new() -> Self {
+        List::new_in(RcKind)
+    }
+}
+
+impl<T, P: PointerKind> List<T, P> {
+    pub fn new_in(_kind: P) -> Self {
         List { head: None }
     }
 
-    pub fn append(&self, elem: T) -> List<T> {
+    pub fn append(&self, elem: T) -> List<T, P> {
         List {
-            head: Some(Rc::new(Node {
-                elem: elem,
+            head: Some(P::new(Node {
+                elem,
                 next: self.head.clone(),
-                size: self.size() + 1,
+                len: self.len() + 1,
             })),
         }
     }
 
-    pub fn tail(&self) -> List<T> {
+    pub fn tail(&self) -> List<T, P> {
         List {
             head: self.head.as_ref().and_then(|node| node.next.clone()),
         }
@@ -37,39 +91,287 @@ impl<T> List<T> {
         self.head.is_none()
     }
 
+    pub fn len(&self) -> usize {
+        self.head.as_ref().map(|node| node.len).unwrap_or_default()
+    }
+
+    #[deprecated(note = "use `len` instead")]
     pub fn size(&self) -> u32 {
-        self.head.as_ref().map(|node| node.size).unwrap_or_default()
+        self.len() as u32
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T, P> {
         Iter {
-            next: self.head.as_ref().map(|node| &**node),
+            next: self.head.as_deref(),
+        }
+    }
+
+    // The combinators below return new lists and leave `self` untouched.
+    // Nodes can only be shared from some point to the end, so those that
+    // keep the order copy the elements up to the last node they change and
+    // share everything after it.
+
+    pub fn fold<B, F: FnMut(B, &T) -> B>(&self, init: B, f: F) -> B {
+        self.iter().fold(init, f)
+    }
+
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> List<U, P> {
+        self.iter().map(f).collect()
+    }
+
+    // Shares the longest suffix in which nothing was filtered out.
+    pub fn filter<F: FnMut(&T) -> bool>(&self, mut f: F) -> List<T, P>
+    where
+        T: Clone,
+    {
+        let mut kept = Vec::new();
+        let mut shared = self.head.clone();
+        let mut prefix = 0;
+        let mut link = self.head.as_deref();
+        while let Some(node) = link {
+            if f(&node.elem) {
+                kept.push(&node.elem);
+            } else {
+                prefix = kept.len();
+                shared = node.next.clone();
+            }
+            link = node.next.as_deref();
+        }
+        kept.truncate(prefix);
+        Self::cons_all(kept, List { head: shared })
+    }
+
+    pub fn reverse(&self) -> List<T, P>
+    where
+        T: Clone,
+    {
+        self.iter()
+            .fold(List::default(), |list, elem| list.append(elem.clone()))
+    }
+
+    pub fn take(&self, n: usize) -> List<T, P>
+    where
+        T: Clone,
+    {
+        if n >= self.len() {
+            return self.clone();
+        }
+        Self::cons_all(self.iter().take(n).collect(), List::default())
+    }
+
+    // Shares the whole remaining suffix, so nothing is copied.
+    pub fn drop(&self, n: usize) -> List<T, P> {
+        self.skip(n)
+    }
+
+    // Copies `self` in front of `other`, which is shared whole.
+    pub fn concat(&self, other: &List<T, P>) -> List<T, P>
+    where
+        T: Clone,
+    {
+        Self::cons_all(self.iter().collect(), other.clone())
+    }
+
+    pub fn update(&self, index: usize, elem: T) -> List<T, P>
+    where
+        T: Clone,
+    {
+        self.check_index(index);
+        let prefix = self.iter().take(index).collect();
+        Self::cons_all(prefix, self.skip(index + 1).append(elem))
+    }
+
+    pub fn remove(&self, index: usize) -> List<T, P>
+    where
+        T: Clone,
+    {
+        self.check_index(index);
+        let prefix = self.iter().take(index).collect();
+        Self::cons_all(prefix, self.skip(index + 1))
+    }
+
+    fn skip(&self, n: usize) -> List<T, P> {
+        let mut link = self.head.as_ref();
+        for _ in 0..n {
+            link = match link {
+                Some(node) => node.next.as_ref(),
+                None => break,
+            };
+        }
+        List {
+            head: link.cloned(),
         }
     }
+
+    fn check_index(&self, index: usize) {
+        let len = self.len();
+        assert!(
+            index < len,
+            "index out of bounds: the len is {} but the index is {}",
+            len,
+            index
+        );
+    }
+
+    // Conses clones of `prefix` onto `tail`, keeping their order.
+    fn cons_all(prefix: Vec<&T>, tail: List<T, P>) -> List<T, P>
+    where
+        T: Clone,
+    {
+        prefix
+            .into_iter()
+            .rev()
+            .fold(tail, |list, elem| list.append(elem.clone()))
+    }
 }
 
-pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+pub struct Iter<'a, T, P: PointerKind = RcKind> {
+    next: Option<&'a Node<T, P>>,
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
+impl<'a, T, P: PointerKind> Iterator for Iter<'a, T, P> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
         let result = self.next.map(|node| &node.elem);
-        self.next = self
-            .next
-            .and_then(|node| node.next.as_ref().map(|node| &**node));
+        self.next = self.next.and_then(|node| node.next.as_deref());
         result
     }
 }
 
-impl<T> Drop for List<T> {
+// Moves elements out of the nodes the list owns alone and clones them out of
+// the ones it shares with other lists.
+impl<T: Clone, P: PointerKind> IntoIterator for List<T, P> {
+    type Item = T;
+    type IntoIter = IntoIter<T, P>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T, P: PointerKind = RcKind>(List<T, P>);
+
+impl<T: Clone, P: PointerKind> Iterator for IntoIter<T, P> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_ref = self.0.head.take()?;
+        match P::try_unwrap(node_ref) {
+            Ok(node) => {
+                self.0.head = node.next;
+                Some(node.elem)
+            }
+            Err(node_ref) => {
+                self.0.head = node_ref.next.clone();
+                Some(node_ref.elem.clone())
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.len();
+        (len, Some(len))
+    }
+}
+
+impl<T: Clone, P: PointerKind> ExactSizeIterator for IntoIter<T, P> {}
+
+impl<T: Clone, P: PointerKind> FusedIterator for IntoIter<T, P> {}
+
+impl<'a, T, P: PointerKind> IntoIterator for &'a List<T, P> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Extending adds at the back, like std's `LinkedList`. The existing nodes
+// may be shared with other lists, so they are copied in front of the new
+// ones rather than changed.
+impl<T: Clone, P: PointerKind> Extend<T> for List<T, P> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let back: List<T, P> = iter.into_iter().collect();
+        if !back.is_empty() {
+            *self = self.concat(&back);
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy, P: PointerKind> Extend<&'a T> for List<T, P> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+// Collecting keeps the iteration order, like std's `LinkedList`.
+impl<T, P: PointerKind> FromIterator<T> for List<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let elems: Vec<T> = iter.into_iter().collect();
+        elems
+            .into_iter()
+            .rev()
+            .fold(List::default(), |list, elem| list.append(elem))
+    }
+}
+
+impl<T, P: PointerKind> Clone for List<T, P> {
+    fn clone(&self) -> Self {
+        List {
+            head: self.head.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, P: PointerKind> fmt::Debug for List<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq, P: PointerKind> PartialEq for List<T, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, P: PointerKind> Eq for List<T, P> {}
+
+impl<T: PartialOrd, P: PointerKind> PartialOrd for List<T, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord, P: PointerKind> Ord for List<T, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash, P: PointerKind> Hash for List<T, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<T, P: PointerKind> Default for List<T, P> {
+    fn default() -> Self {
+        List { head: None }
+    }
+}
+
+// `into_inner` rather than `try_unwrap`: when two threads drop the last two
+// handles to a node at once, both `try_unwrap`s can fail and the node would
+// then be freed by a recursive drop of its `next`.
+impl<T, P: PointerKind> Drop for List<T, P> {
     fn drop(&mut self) {
         let mut curr_link = self.head.take();
         while let Some(node_ref) = curr_link {
-            match Rc::try_unwrap(node_ref) {
-                Ok(node) => curr_link = node.next,
-                Err(_) => break,
+            match P::into_inner(node_ref) {
+                Some(node) => curr_link = node.next,
+                None => break,
             }
         }
     }
@@ -78,13 +380,16 @@ impl<T> Drop for List<T> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::sync::Barrier;
+    use std::thread;
     use testdrop::TestDrop;
 
     #[test]
     fn test_empty_on_creation() {
         let list: List<i32> = List::new();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
     }
 
     #[test]
@@ -94,11 +399,11 @@ mod test {
         list = list.append(2);
         list = list.append(3);
         list = list.append(4);
-        assert_eq!(list.size(), 4);
-        assert_eq!(list.tail().size(), 3);
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.tail().len(), 3);
         list = list.tail().tail().tail().tail();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
     }
 
     #[test]
@@ -134,6 +439,241 @@ mod test {
     fn test_tail_on_empty() {
         let list: List<i32> = List::new().tail();
         assert!(list.is_empty());
-        assert_eq!(list.size(), 0);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn test_collect_extend() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.len(), 3);
+        let snapshot = list.clone();
+        list.extend(vec![4, 5]);
+        list.extend(&[6]);
+        list.extend(Vec::<i32>::new());
+        let mut elems = Vec::new();
+        for &elem in &list {
+            elems.push(elem);
+        }
+        assert_eq!(elems, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(snapshot.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let list: List<Rc<i32>> = (0..3).map(Rc::new).collect();
+        let shared = list.drop(1);
+
+        // The first node is moved out; the other two are cloned out of the
+        // nodes `shared` still holds.
+        let iter = list.into_iter();
+        assert_eq!(iter.len(), 3);
+        let elems: Vec<_> = iter.collect();
+        let counts: Vec<_> = elems.iter().map(Rc::strong_count).collect();
+        assert_eq!(counts, vec![1, 2, 2]);
+        drop(shared);
+        assert!(elems.iter().all(|elem| Rc::strong_count(elem) == 1));
+
+        let mut sum = 0;
+        for elem in (1..=4).collect::<List<i32>>() {
+            sum += elem;
+        }
+        assert_eq!(sum, 10);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+        assert!(Rc::ptr_eq(
+            list.head.as_ref().unwrap(),
+            copy.head.as_ref().unwrap()
+        ));
+        assert_eq!(list, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let shorter = list.tail();
+        let bigger = shorter.append(4);
+        assert_ne!(list, shorter);
+        assert!(list < shorter);
+        assert!(list < bigger);
+        assert!(shorter < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+    }
+
+    // The node `n` steps into `list`, for checking sharing with `Rc::ptr_eq`.
+    fn nth_node<T, P: PointerKind>(list: &List<T, P>, n: usize) -> &P::Pointer<Node<T, P>> {
+        let mut node = list.head.as_ref().unwrap();
+        for _ in 0..n {
+            node = node.next.as_ref().unwrap();
+        }
+        node
+    }
+
+    fn to_vec<T: Copy, P: PointerKind>(list: &List<T, P>) -> Vec<T> {
+        list.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_map_fold_reverse() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(to_vec(&list.map(|x| x * 10)), vec![10, 20, 30]);
+        assert_eq!(list.map(|x| x.to_string()).len(), 3);
+        assert_eq!(list.fold(0, |acc, x| acc * 10 + x), 123);
+        assert_eq!(to_vec(&list.reverse()), vec![3, 2, 1]);
+        assert!(List::<i32>::new().reverse().is_empty());
+        assert_eq!(to_vec(&list), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_filter_shares_suffix() {
+        let list: List<i32> = vec![1, 2, 3, 4, 5, 7, 9].into_iter().collect();
+        let odd = list.filter(|x| x % 2 == 1);
+        assert_eq!(to_vec(&odd), vec![1, 3, 5, 7, 9]);
+        // Everything after the last removed element, 4, is shared.
+        assert!(Rc::ptr_eq(nth_node(&odd, 2), nth_node(&list, 4)));
+
+        let all = list.filter(|_| true);
+        assert!(Rc::ptr_eq(nth_node(&all, 0), nth_node(&list, 0)));
+        assert!(list.filter(|_| false).is_empty());
+        let head_removed = list.filter(|&x| x != 1);
+        assert!(Rc::ptr_eq(nth_node(&head_removed, 0), nth_node(&list, 1)));
+    }
+
+    #[test]
+    fn test_take_drop() {
+        let list: List<i32> = vec![1, 2, 3, 4].into_iter().collect();
+        assert_eq!(to_vec(&list.take(2)), vec![1, 2]);
+        assert!(list.take(0).is_empty());
+        assert!(Rc::ptr_eq(nth_node(&list.take(9), 0), nth_node(&list, 0)));
+
+        let dropped = list.drop(1);
+        assert_eq!(to_vec(&dropped), vec![2, 3, 4]);
+        assert!(Rc::ptr_eq(nth_node(&dropped, 0), nth_node(&list, 1)));
+        assert!(list.drop(4).is_empty());
+        assert!(list.drop(9).is_empty());
+    }
+
+    #[test]
+    fn test_concat() {
+        let front: List<i32> = vec![1, 2].into_iter().collect();
+        let back: List<i32> = vec![3, 4].into_iter().collect();
+        let both = front.concat(&back);
+        assert_eq!(to_vec(&both), vec![1, 2, 3, 4]);
+        assert_eq!(both.len(), 4);
+        assert!(Rc::ptr_eq(nth_node(&both, 2), nth_node(&back, 0)));
+        assert!(Rc::ptr_eq(
+            nth_node(&List::new().concat(&back), 0),
+            nth_node(&back, 0)
+        ));
+        assert_eq!(to_vec(&front.concat(&List::new())), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_update_remove() {
+        let list: List<i32> = vec![1, 2, 3, 4].into_iter().collect();
+        let updated = list.update(1, 20);
+        assert_eq!(to_vec(&updated), vec![1, 20, 3, 4]);
+        assert!(Rc::ptr_eq(nth_node(&updated, 2), nth_node(&list, 2)));
+        assert_eq!(updated.len(), 4);
+
+        let removed = list.remove(1);
+        assert_eq!(to_vec(&removed), vec![1, 3, 4]);
+        assert!(Rc::ptr_eq(nth_node(&removed, 1), nth_node(&list, 2)));
+        assert_eq!(to_vec(&list.remove(3)), vec![1, 2, 3]);
+        assert_eq!(to_vec(&list), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 2 but the index is 2")]
+    fn test_update_out_of_bounds() {
+        List::new().append(1).append(2).update(2, 0);
+    }
+
+    #[test]
+    fn test_combinator_drops() {
+        let testdrop = TestDrop::new();
+        let list: List<_> = (0..4).map(|_| testdrop.new_item()).collect();
+        let dropped = list.drop(2);
+        drop(list);
+        assert_eq!(testdrop.num_dropped_items(), 2);
+        drop(dropped);
+        assert_eq!(testdrop.num_dropped_items(), 4);
+    }
+
+    #[test]
+    fn test_arc_snapshots() {
+        let base: List<i32, ArcKind> = vec![1, 2, 3].into_iter().collect();
+        let versions: Vec<List<i32, ArcKind>> = thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|i| {
+                    let base = base.clone();
+                    s.spawn(move || base.tail().append(i * 10))
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        for (i, version) in versions.iter().enumerate() {
+            assert_eq!(to_vec(version), vec![i as i32 * 10, 2, 3]);
+            assert!(Arc::ptr_eq(nth_node(version, 1), nth_node(&base, 1)));
+        }
+        assert_eq!(to_vec(&base.remove(0)), vec![2, 3]);
+        assert_eq!(List::new_in(ArcKind).append(1), base.take(1));
+    }
+
+    #[test]
+    fn test_long_list_drop() {
+        const LEN: usize = if cfg!(miri) { 1_000 } else { 200_000 };
+        let rc: List<usize> = (0..LEN).collect();
+        let arc: List<usize, ArcKind> = (0..LEN).collect();
+        let arc_tail = arc.drop(LEN / 2);
+        drop(rc);
+        drop(arc);
+        assert_eq!(arc_tail.len(), LEN / 2);
+    }
+
+    // Every thread drops its handle to the same long list at once, so
+    // whichever drops last frees all of it.
+    #[test]
+    fn test_shared_arc_drop() {
+        const LEN: usize = if cfg!(miri) { 1_000 } else { 200_000 };
+        const THREADS: usize = 4;
+        let list: List<usize, ArcKind> = (0..LEN).collect();
+        let barrier = Barrier::new(THREADS);
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                let (list, barrier) = (list.clone(), &barrier);
+                s.spawn(move || {
+                    barrier.wait();
+                    drop(list);
+                });
+            }
+            drop(list);
+        });
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32, ArcKind>>();
+        is_sync::<List<i32, ArcKind>>();
+        is_send::<Iter<'static, i32, ArcKind>>();
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_size_alias() {
+        let list = List::new().append(1).append(2);
+        assert_eq!(list.size(), 2);
     }
 }
@@ -0,0 +1,847 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ops::{Index, IndexMut};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+// A doubly-linked list of nodes that each store up to `N` elements inline,
+// so small elements pay for a node's links and allocation once per `N`
+// elements instead of once each. The list never keeps empty nodes around.
+pub struct List<T, const N: usize> {
+    head: Link<T, N>,
+    tail: Link<T, N>,
+    len: usize,
+    marker: PhantomData<Box<Node<T, N>>>,
+}
+
+type Link<T, const N: usize> = Option<NonNull<Node<T, N>>>;
+
+// The first `len` slots of `elems` are initialized.
+struct Node<T, const N: usize> {
+    elems: [MaybeUninit<T>; N],
+    len: usize,
+    next: Link<T, N>,
+    prev: Link<T, N>,
+}
+
+impl<T, const N: usize> Node<T, N> {
+    fn alloc() -> NonNull<Self> {
+        let node = Box::new(Node {
+            // An array of `MaybeUninit` needs no initialization.
+            elems: unsafe { MaybeUninit::uninit().assume_init() },
+            len: 0,
+            next: None,
+            prev: None,
+        });
+        unsafe { NonNull::new_unchecked(Box::into_raw(node)) }
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.elems.as_ptr() as *const T, self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.elems.as_mut_ptr() as *mut T, self.len) }
+    }
+
+    // `index` must be at most `len` and the node must not be full.
+    unsafe fn insert(&mut self, index: usize, elem: T) {
+        let p = self.elems.as_mut_ptr().add(index);
+        ptr::copy(p, p.add(1), self.len - index);
+        (*p).write(elem);
+        self.len += 1;
+    }
+
+    // `index` must be less than `len`.
+    unsafe fn remove(&mut self, index: usize) -> T {
+        self.len -= 1;
+        let p = self.elems.as_mut_ptr().add(index);
+        let elem = (*p).assume_init_read();
+        ptr::copy(p.add(1), p, self.len - index);
+        elem
+    }
+
+    // Moves the elements from `at` on to the end of `other`, which must have
+    // room for them.
+    unsafe fn move_tail_to(&mut self, at: usize, other: &mut Self) {
+        let count = self.len - at;
+        ptr::copy_nonoverlapping(
+            self.elems.as_ptr().add(at),
+            other.elems.as_mut_ptr().add(other.len),
+            count,
+        );
+        self.len = at;
+        other.len += count;
+    }
+}
+
+impl<T, const N: usize> List<T, N> {
+    const NONZERO: () = assert!(N > 0, "unrolled nodes must hold at least one element");
+
+    pub fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NONZERO;
+        List {
+            head: None,
+            tail: None,
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        let node = match self.tail {
+            Some(tail) if unsafe { !(*tail.as_ptr()).is_full() } => tail,
+            _ => unsafe { self.link_after(self.tail) },
+        };
+        unsafe {
+            let node = &mut *node.as_ptr();
+            node.insert(node.len, elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        let node = match self.head {
+            Some(head) if unsafe { !(*head.as_ptr()).is_full() } => head,
+            _ => unsafe { self.link_after(None) },
+        };
+        unsafe { (*node.as_ptr()).insert(0, elem) };
+        self.len += 1;
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tail.map(|node| unsafe {
+            let index = (*node.as_ptr()).len - 1;
+            self.remove_at(node, index)
+        })
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.map(|node| unsafe { self.remove_at(node, 0) })
+    }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        self.head
+            .map(|node| unsafe { &(*node.as_ptr()).as_slice()[0] })
+    }
+
+    pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+        self.head
+            .map(|node| unsafe { &mut (*node.as_ptr()).as_mut_slice()[0] })
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        self.tail
+            .and_then(|node| unsafe { (*node.as_ptr()).as_slice().last() })
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+        self.tail
+            .and_then(|node| unsafe { (*node.as_ptr()).as_mut_slice().last_mut() })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            let (node, offset) = self.locate(index);
+            Some(unsafe { &(*node.as_ptr()).as_slice()[offset] })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            let (node, offset) = self.locate(index);
+            Some(unsafe { &mut (*node.as_ptr()).as_mut_slice()[offset] })
+        } else {
+            None
+        }
+    }
+
+    // Splits a full node in two halves before inserting into it.
+    pub fn insert(&mut self, index: usize, elem: T) {
+        assert!(
+            index <= self.len,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.len
+        );
+
+        if index == self.len {
+            return self.push_back(elem);
+        }
+
+        let (mut node, mut offset) = self.locate(index);
+        unsafe {
+            if (*node.as_ptr()).is_full() {
+                let half = N / 2;
+                let new_node = self.link_after(Some(node));
+                (*node.as_ptr()).move_tail_to(half, &mut *new_node.as_ptr());
+                if offset > half {
+                    node = new_node;
+                    offset -= half;
+                }
+            }
+            (*node.as_ptr()).insert(offset, elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index < self.len {
+            let (node, offset) = self.locate(index);
+            Some(unsafe { self.remove_at(node, offset) })
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        while let Some(node) = self.head {
+            unsafe {
+                self.unlink(node);
+                let mut node = Box::from_raw(node.as_ptr());
+                self.len -= node.len;
+                ptr::drop_in_place(node.as_mut_slice());
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter {
+            head: self.head,
+            head_index: 0,
+            tail: self.tail,
+            tail_index: self.tail.map_or(0, |tail| unsafe { (*tail.as_ptr()).len }),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, N> {
+        IterMut {
+            head: self.head,
+            head_index: 0,
+            tail: self.tail,
+            tail_index: self.tail.map_or(0, |tail| unsafe { (*tail.as_ptr()).len }),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    // Finds the node holding the element at `index` and the element's offset in
+    // it, walking from whichever end is closer. `index` must be less than `len`.
+    fn locate(&self, index: usize) -> (NonNull<Node<T, N>>, usize) {
+        unsafe {
+            if index < self.len / 2 {
+                let mut node = self.head.unwrap();
+                let mut offset = index;
+                while offset >= (*node.as_ptr()).len {
+                    offset -= (*node.as_ptr()).len;
+                    node = (*node.as_ptr()).next.unwrap();
+                }
+                (node, offset)
+            } else {
+                let mut node = self.tail.unwrap();
+                let mut from_back = self.len - index;
+                while from_back > (*node.as_ptr()).len {
+                    from_back -= (*node.as_ptr()).len;
+                    node = (*node.as_ptr()).prev.unwrap();
+                }
+                (node, (*node.as_ptr()).len - from_back)
+            }
+        }
+    }
+
+    // Removes the element at `offset` in `node`. A node that drops below half
+    // full is merged into a neighbour when their elements fit in one node, and
+    // an empty node is freed. `node` must belong to this list.
+    unsafe fn remove_at(&mut self, node: NonNull<Node<T, N>>, offset: usize) -> T {
+        let elem = (*node.as_ptr()).remove(offset);
+        self.len -= 1;
+
+        let len = (*node.as_ptr()).len;
+        if len == 0 {
+            self.unlink(node);
+            drop(Box::from_raw(node.as_ptr()));
+        } else if len < N / 2 {
+            let next = (*node.as_ptr()).next;
+            let prev = (*node.as_ptr()).prev;
+            if let Some(next) = next.filter(|next| len + (*next.as_ptr()).len <= N) {
+                (*next.as_ptr()).move_tail_to(0, &mut *node.as_ptr());
+                self.unlink(next);
+                drop(Box::from_raw(next.as_ptr()));
+            } else if let Some(prev) = prev.filter(|prev| len + (*prev.as_ptr()).len <= N) {
+                (*node.as_ptr()).move_tail_to(0, &mut *prev.as_ptr());
+                self.unlink(node);
+                drop(Box::from_raw(node.as_ptr()));
+            }
+        }
+        elem
+    }
+
+    // Links a new, empty node right after `prev`, or at the front when `prev`
+    // is `None`. `prev` must be `None` or a node owned by this list.
+    unsafe fn link_after(&mut self, prev: Link<T, N>) -> NonNull<Node<T, N>> {
+        let node = Node::alloc();
+        let next = match prev {
+            Some(prev) => (*prev.as_ptr()).next,
+            None => self.head,
+        };
+        (*node.as_ptr()).prev = prev;
+        (*node.as_ptr()).next = next;
+
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = Some(node),
+            None => self.head = Some(node),
+        }
+        match next {
+            Some(next) => (*next.as_ptr()).prev = Some(node),
+            None => self.tail = Some(node),
+        }
+        node
+    }
+
+    // Detaches `node` from the chain without touching its elements.
+    unsafe fn unlink(&mut self, node: NonNull<Node<T, N>>) {
+        let node = &mut *node.as_ptr();
+        match node.prev {
+            Some(prev) => (*prev.as_ptr()).next = node.next,
+            None => self.head = node.next,
+        }
+        match node.next {
+            Some(next) => (*next.as_ptr()).prev = node.prev,
+            None => self.tail = node.prev,
+        }
+        node.next = None;
+        node.prev = None;
+    }
+}
+
+unsafe impl<T: Send, const N: usize> Send for List<T, N> {}
+unsafe impl<T: Sync, const N: usize> Sync for List<T, N> {}
+
+impl<T, const N: usize> IntoIterator for List<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+pub struct IntoIter<T, const N: usize>(List<T, N>);
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for IntoIter<T, N> {}
+
+// The borrowing iterators track a position in the head and tail nodes, with
+// `tail_index` one past the next element from the back, and count down the
+// elements left so the two ends never meet.
+pub struct Iter<'a, T, const N: usize> {
+    head: Link<T, N>,
+    head_index: usize,
+    tail: Link<T, N>,
+    tail_index: usize,
+    len: usize,
+    marker: PhantomData<&'a Node<T, N>>,
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.head.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            let elem = &node.as_slice()[self.head_index];
+            self.len -= 1;
+            self.head_index += 1;
+            if self.head_index == node.len {
+                self.head = node.next;
+                self.head_index = 0;
+            }
+            elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T, const N: usize> DoubleEndedIterator for Iter<'a, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.tail.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            self.len -= 1;
+            self.tail_index -= 1;
+            let elem = &node.as_slice()[self.tail_index];
+            if self.tail_index == 0 {
+                self.tail = node.prev;
+                self.tail_index = node.prev.map_or(0, |prev| (*prev.as_ptr()).len);
+            }
+            elem
+        })
+    }
+}
+
+impl<'a, T, const N: usize> ExactSizeIterator for Iter<'a, T, N> {}
+
+impl<'a, T, const N: usize> FusedIterator for Iter<'a, T, N> {}
+
+unsafe impl<'a, T: Sync, const N: usize> Send for Iter<'a, T, N> {}
+unsafe impl<'a, T: Sync, const N: usize> Sync for Iter<'a, T, N> {}
+
+// Elements are reached through raw pointers to the node's storage so that
+// handing out one `&mut T` never reborrows the elements handed out before.
+pub struct IterMut<'a, T, const N: usize> {
+    head: Link<T, N>,
+    head_index: usize,
+    tail: Link<T, N>,
+    tail_index: usize,
+    len: usize,
+    marker: PhantomData<&'a mut Node<T, N>>,
+}
+
+impl<'a, T, const N: usize> Iterator for IterMut<'a, T, N> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.head.map(|node| unsafe {
+            let node = node.as_ptr();
+            let elem = &mut *(ptr::addr_of_mut!((*node).elems) as *mut T).add(self.head_index);
+            self.len -= 1;
+            self.head_index += 1;
+            if self.head_index == (*node).len {
+                self.head = (*node).next;
+                self.head_index = 0;
+            }
+            elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T, const N: usize> DoubleEndedIterator for IterMut<'a, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.tail.map(|node| unsafe {
+            let node = node.as_ptr();
+            self.len -= 1;
+            self.tail_index -= 1;
+            let elem = &mut *(ptr::addr_of_mut!((*node).elems) as *mut T).add(self.tail_index);
+            if self.tail_index == 0 {
+                self.tail = (*node).prev;
+                self.tail_index = (*node).prev.map_or(0, |prev| (*prev.as_ptr()).len);
+            }
+            elem
+        })
+    }
+}
+
+impl<'a, T, const N: usize> ExactSizeIterator for IterMut<'a, T, N> {}
+
+impl<'a, T, const N: usize> FusedIterator for IterMut<'a, T, N> {}
+
+unsafe impl<'a, T: Send, const N: usize> Send for IterMut<'a, T, N> {}
+unsafe impl<'a, T: Sync, const N: usize> Sync for IterMut<'a, T, N> {}
+
+impl<'a, T, const N: usize> IntoIterator for &'a List<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut List<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T, N>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T, const N: usize> Extend<T> for List<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy, const N: usize> Extend<&'a T> for List<T, N> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for List<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for List<T, N> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for List<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for List<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for List<T, N> {}
+
+impl<T: PartialOrd, const N: usize> PartialOrd for List<T, N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord, const N: usize> Ord for List<T, N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash, const N: usize> Hash for List<T, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<T, const N: usize> Index<usize> for List<T, N> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        let len = self.len;
+        self.get(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
+        })
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for List<T, N> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let len = self.len;
+        self.get_mut(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
+        })
+    }
+}
+
+impl<T, const N: usize> Default for List<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for List<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use testdrop::TestDrop;
+
+    // Checks the links, the cached lengths and that no node is empty.
+    fn check<T, const N: usize>(list: &List<T, N>) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let mut prev = None;
+        let mut curr = list.head;
+        while let Some(node) = curr {
+            unsafe {
+                assert_eq!((*node.as_ptr()).prev, prev);
+                assert!((*node.as_ptr()).len > 0);
+                sizes.push((*node.as_ptr()).len);
+                prev = curr;
+                curr = (*node.as_ptr()).next;
+            }
+        }
+        assert_eq!(list.tail, prev);
+        assert_eq!(sizes.iter().sum::<usize>(), list.len());
+        sizes
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut list: List<i32, 3> = List::new();
+        assert_eq!(list.pop_front(), None);
+        for i in 0..5 {
+            list.push_back(i);
+            list.push_front(-i - 1);
+        }
+        assert_eq!(check(&list), vec![1, 3, 3, 3]);
+        assert_eq!(list.len(), 10);
+        assert_eq!(list.peek_front(), Some(&-5));
+        assert_eq!(list.peek_back(), Some(&4));
+
+        assert_eq!(list.pop_back(), Some(4));
+        assert_eq!(list.pop_front(), Some(-5));
+        *list.peek_front_mut().unwrap() *= 10;
+        *list.peek_back_mut().unwrap() *= 10;
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            vec![-40, -3, -2, -1, 0, 1, 2, 30]
+        );
+        check(&list);
+
+        while list.pop_back().is_some() {}
+        assert!(list.is_empty());
+        assert_eq!(check(&list), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_get() {
+        let mut list: List<i32, 4> = (0..10).collect();
+        for i in 0..10 {
+            assert_eq!(list.get(i as usize), Some(&i));
+        }
+        assert_eq!(list.get(10), None);
+        *list.get_mut(9).unwrap() = 90;
+        list[0] = -1;
+        assert_eq!(list[9], 90);
+        assert_eq!(list[0], -1);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 2 but the index is 2")]
+    fn test_index_out_of_bounds() {
+        let list: List<i32, 4> = vec![1, 2].into_iter().collect();
+        let _ = list[2];
+    }
+
+    #[test]
+    fn test_insert_splits() {
+        let mut list: List<i32, 4> = (0..4).collect();
+        assert_eq!(check(&list), vec![4]);
+        list.insert(1, 10);
+        assert_eq!(check(&list), vec![3, 2]);
+        list.insert(5, 20);
+        list.insert(4, 30);
+        assert_eq!(check(&list), vec![3, 4]);
+        list.insert(6, 40);
+        assert_eq!(check(&list), vec![3, 2, 3]);
+        list.insert(0, 50);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            vec![50, 0, 10, 1, 2, 30, 3, 40, 20]
+        );
+
+        let mut vec: Vec<_> = list.iter().copied().collect();
+        for i in 0..40 {
+            let index = (i * 7) % (vec.len() + 1);
+            list.insert(index, i as i32);
+            vec.insert(index, i as i32);
+            check(&list);
+        }
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec);
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 3) should be <= len (is 2)")]
+    fn test_insert_out_of_bounds() {
+        let mut list: List<i32, 4> = vec![1, 2].into_iter().collect();
+        list.insert(3, 3);
+    }
+
+    #[test]
+    fn test_remove_merges() {
+        let mut list: List<i32, 4> = (0..12).collect();
+        assert_eq!(check(&list), vec![4, 4, 4]);
+        assert_eq!(list.remove(5), Some(5));
+        assert_eq!(list.remove(4), Some(4));
+        assert_eq!(check(&list), vec![4, 2, 4]);
+        assert_eq!(list.remove(4), Some(6));
+        assert_eq!(check(&list), vec![4, 1, 4]);
+        assert_eq!(list.remove(0), Some(0));
+        assert_eq!(list.remove(0), Some(1));
+        assert_eq!(list.remove(0), Some(2));
+        assert_eq!(check(&list), vec![2, 4]);
+        assert_eq!(list.remove(8), None);
+
+        let mut vec: Vec<_> = list.iter().copied().collect();
+        let mut list: List<i32, 4> = (0..100).collect();
+        vec = (0..100)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .chain(vec)
+            .collect();
+        list.extend(vec[100..].iter());
+        for i in 0..80 {
+            let index = (i * 13) % vec.len();
+            assert_eq!(list.remove(index), Some(vec.remove(index)));
+            check(&list);
+        }
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut list: List<i32, 3> = List::new();
+        for i in (0..4).rev() {
+            list.push_front(i);
+        }
+        list.extend(vec![4, 5, 6, 7]);
+        assert_eq!(check(&list), vec![1, 3, 3, 1]);
+
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 8);
+        assert_eq!(iter.next_back(), Some(&7));
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&6));
+        assert_eq!(iter.collect::<Vec<_>>(), vec![&1, &2, &3, &4, &5]);
+        assert_eq!(
+            list.iter().rev().copied().collect::<Vec<_>>(),
+            vec![7, 6, 5, 4, 3, 2, 1, 0]
+        );
+
+        for elem in &mut list {
+            *elem *= 2;
+        }
+        let mut iter = list.iter_mut();
+        *iter.next_back().unwrap() += 1;
+        *iter.next().unwrap() += 1;
+        assert_eq!(iter.rev().count(), 6);
+        assert_eq!(
+            list.into_iter().rev().collect::<Vec<_>>(),
+            vec![15, 12, 10, 8, 6, 4, 2, 1]
+        );
+    }
+
+    #[test]
+    fn test_drops() {
+        let testdrop = TestDrop::new();
+        let mut list: List<_, 4> = List::new();
+        for _ in 0..10 {
+            list.push_back(testdrop.new_item());
+        }
+        list.remove(3);
+        list.pop_front();
+        list.insert(2, testdrop.new_item());
+        assert_eq!(2, testdrop.num_dropped_items());
+        drop(list);
+        assert_eq!(11, testdrop.num_dropped_items());
+    }
+
+    #[test]
+    fn test_zero_sized() {
+        let mut list: List<(), 8> = (0..20).map(|_| ()).collect();
+        assert_eq!(check(&list), vec![8, 8, 4]);
+        list.insert(3, ());
+        assert_eq!(list.remove(7), Some(()));
+        assert_eq!(list.iter().count(), 20);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let list: List<i32, 2> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+        assert_eq!(list, copy);
+        assert_eq!(format!("{:?}", copy), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32, 2>::default()), "[]");
+        assert_eq!(hash_of(&list), hash_of(&copy));
+
+        let mut shorter: List<i32, 2> = List::new();
+        shorter.extend(&[1, 2]);
+        let bigger: List<i32, 2> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(bigger.partial_cmp(&shorter), Some(Ordering::Greater));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32, 4>>();
+        is_sync::<List<i32, 4>>();
+        is_send::<Iter<'static, i32, 4>>();
+        is_sync::<Iter<'static, i32, 4>>();
+        is_send::<IterMut<'static, i32, 4>>();
+        is_sync::<IterMut<'static, i32, 4>>();
+    }
+}